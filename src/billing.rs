@@ -0,0 +1,242 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::tag::Tag;
+use crate::time_summary::{TagTime, find_tag_time};
+
+/// Billing settings of a tag. Unset fields are inherited from the
+/// closest parent tag that sets them, so `client/acme/internal` can
+/// e.g. only override `billable`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagMeta {
+    pub billable: Option<bool>,
+    /// Hourly rate
+    pub rate: Option<f64>,
+    pub currency: Option<String>,
+    /// Billed time is rounded up to a multiple of this many minutes
+    pub rounding: Option<u32>,
+}
+
+impl TagMeta {
+    fn inherit_from(self, parent: &TagMeta) -> TagMeta {
+        TagMeta {
+            billable: self.billable.or(parent.billable),
+            rate: self.rate.or(parent.rate),
+            currency: self.currency.or_else(|| parent.currency.clone()),
+            rounding: self.rounding.or(parent.rounding),
+        }
+    }
+
+    pub fn is_billable(&self) -> bool {
+        self.billable.unwrap_or(false)
+    }
+
+    /// Rounds `minutes` up to the rounding increment, if one is set
+    pub fn billed_minutes(&self, minutes: u32) -> u32 {
+        match self.rounding {
+            Some(increment) if increment > 0 => minutes.div_ceil(increment) * increment,
+            _ => minutes,
+        }
+    }
+
+    pub fn amount(&self, billed_minutes: u32) -> Option<f64> {
+        self.rate.map(|rate| rate * billed_minutes as f64 / 60.0)
+    }
+}
+
+/// The `[tags.meta]` section, mapping tag paths to their billing settings
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "HashMap<String, TagMeta>")]
+pub struct TagMetas {
+    metas: Vec<(Vec<String>, TagMeta)>,
+}
+
+impl TryFrom<HashMap<String, TagMeta>> for TagMetas {
+    type Error = String;
+
+    fn try_from(map: HashMap<String, TagMeta>) -> Result<Self, Self::Error> {
+        let mut metas = Vec::new();
+        for (tag, meta) in map {
+            let tag: Tag = tag.parse()?;
+            metas.push((tag.tagls, meta));
+        }
+        metas.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(TagMetas { metas })
+    }
+}
+
+impl TagMetas {
+    /// The settings of the tag with these levels, including everything
+    /// inherited from its parents
+    pub fn effective(&self, tagls: &[String]) -> TagMeta {
+        (1..=tagls.len())
+            .filter_map(|n| self.get(&tagls[..n]))
+            .fold(TagMeta::default(), |parent, meta| {
+                meta.clone().inherit_from(&parent)
+            })
+    }
+
+    /// The closest configured tag that is `tagls` or one of its parents
+    pub fn account_of<'a>(&self, tagls: &'a [String]) -> Option<&'a [String]> {
+        (1..=tagls.len())
+            .rev()
+            .map(|n| &tagls[..n])
+            .find(|prefix| self.get(prefix).is_some())
+    }
+
+    fn get(&self, tagls: &[String]) -> Option<&TagMeta> {
+        self.metas
+            .iter()
+            .find(|(t, _)| t.as_slice() == tagls)
+            .map(|(_, meta)| meta)
+    }
+
+    /// Configured tags below `tagls` that have no other configured tag
+    /// in between
+    fn child_accounts(&self, tagls: &[String]) -> Vec<&[String]> {
+        self.metas
+            .iter()
+            .map(|(t, _)| t.as_slice())
+            .filter(|t| t.len() > tagls.len() && t.starts_with(tagls))
+            .filter(|t| self.account_of(&t[..t.len() - 1]) == Some(tagls))
+            .collect()
+    }
+}
+
+/// The billable time and amount of one configured tag
+#[derive(Debug, Clone, PartialEq)]
+pub struct BillingLine {
+    pub account: Vec<String>,
+    pub minutes: u32,
+    pub billed_minutes: u32,
+    pub meta: TagMeta,
+    pub amount: Option<f64>,
+}
+
+/// Computes one line per billable tag in `[tags.meta]`. Time spent on a
+/// configured sub-tag, e.g. `client/acme/internal`, is only counted for
+/// that sub-tag and not for its parent.
+pub fn billing_lines(tag_timings: &[TagTime], metas: &TagMetas) -> Vec<BillingLine> {
+    metas
+        .metas
+        .iter()
+        .filter_map(|(account, _)| {
+            let meta = metas.effective(account);
+            if !meta.is_billable() {
+                return None;
+            }
+            let minutes_of = |tagls: &[String]| {
+                find_tag_time(tag_timings, tagls)
+                    .map(|tt| tt.minutes)
+                    .unwrap_or(0)
+            };
+            let minutes = metas
+                .child_accounts(account)
+                .iter()
+                .fold(minutes_of(account), |m, child| {
+                    m.saturating_sub(minutes_of(child))
+                });
+            if minutes == 0 {
+                return None;
+            }
+            let billed_minutes = meta.billed_minutes(minutes);
+            Some(BillingLine {
+                account: account.clone(),
+                minutes,
+                billed_minutes,
+                amount: meta.amount(billed_minutes),
+                meta,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::time_summary::{Allocation, time_per_tag};
+
+    fn metas(toml_str: &str) -> TagMetas {
+        toml::from_str(toml_str).unwrap()
+    }
+
+    fn tagls(tag: &str) -> Vec<String> {
+        tag.split('/').map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_meta_is_inherited() {
+        let metas = metas(
+            r#"
+            "client" = { currency = "EUR", rounding = 15 }
+            "client/acme" = { billable = true, rate = 100.0 }
+            "client/acme/internal" = { billable = false }
+            "#,
+        );
+
+        assert_eq!(
+            metas.effective(&tagls("client/acme/dev")),
+            TagMeta {
+                billable: Some(true),
+                rate: Some(100.0),
+                currency: Some("EUR".to_string()),
+                rounding: Some(15),
+            }
+        );
+        assert!(
+            !metas
+                .effective(&tagls("client/acme/internal/x"))
+                .is_billable()
+        );
+        assert!(!metas.effective(&tagls("client/other")).is_billable());
+        assert_eq!(
+            metas.account_of(&tagls("client/acme/dev")),
+            Some(tagls("client/acme").as_slice())
+        );
+    }
+
+    #[test]
+    fn test_billed_minutes_are_rounded_up() {
+        let meta = TagMeta {
+            rate: Some(90.0),
+            rounding: Some(15),
+            ..TagMeta::default()
+        };
+
+        assert_eq!(meta.billed_minutes(61), 75);
+        assert_eq!(meta.billed_minutes(60), 60);
+        assert_eq!(meta.amount(90), Some(135.0));
+        assert_eq!(TagMeta::default().billed_minutes(7), 7);
+    }
+
+    #[test]
+    fn test_billing_lines_leave_out_sub_accounts() {
+        let metas = metas(
+            r#"
+            "client/acme" = { billable = true, rate = 100.0, currency = "EUR", rounding = 30 }
+            "client/acme/internal" = { billable = false }
+            "client/globex" = { billable = true }
+            "#,
+        );
+        let blocks = [
+            Block::new("09:00 - 10:10", "work", "@client/acme/dev"),
+            Block::new("10:10 - 11:00", "work", "@client/acme/internal"),
+            Block::new("11:00 - 11:30", "work", "@client/globex"),
+            Block::new("11:30 - 12:00", "work", "@client/initech"),
+        ];
+        let blocks: Vec<&Block> = blocks.iter().collect();
+        let tag_timings = time_per_tag(&blocks, Allocation::Full, &[]);
+
+        let lines = billing_lines(&tag_timings, &metas);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].account, tagls("client/acme"));
+        assert_eq!(lines[0].minutes, 70);
+        assert_eq!(lines[0].billed_minutes, 90);
+        assert_eq!(lines[0].amount, Some(150.0));
+        assert_eq!(lines[1].account, tagls("client/globex"));
+        assert_eq!(lines[1].amount, None);
+    }
+}
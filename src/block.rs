@@ -1,17 +1,52 @@
+use chrono::{Duration, NaiveTime};
 use regex::Regex;
 
+use crate::cal_day_plan::stable_hash;
 use crate::tag::{Tag, parse_tags};
 
+// `all day` must be followed by whitespace or the end, so a description
+// like "all days off" is not taken for an all-day block
 const BLOCKSTRING_REGEX: &str =
-    r"^\s*(\d{2}:\d{2}\s*-\s*\d{2}:\d{2}|\d{2}:\d{2})?\s*(\(([^\)]*)\))?\s*(.*)";
+    r"^\s*(\d{2}:\d{2}\s*-\s*\d{2}:\d{2}|\d{2}:\d{2}|all day(?:\s|$))?\s*(\(([^\)]*)\))?\s*(.*)";
+
+// The UID is escaped so it contains neither whitespace nor `%%`
+const UID_COMMENT_REGEX: &str =
+    r"\s*%%uid:((?:[^%\s]|%[^%\s])+)(?: ([0-9a-f]{8})-([0-9a-f]{8}))?%%\s*$";
+
+/// The period of blocks that take the whole day, like holidays. They
+/// have no time and count as zero minutes.
+pub const ALL_DAY: &str = "all day";
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Block {
     pub period_str: String,
     pub origin: String,
     pub desc: String,
-    pub duration: u16,
+    pub duration: u32,
     pub tags: Vec<Tag>,
+    /// The UID of the calendar event a pulled block was created from,
+    /// kept as a trailing `%%uid:...%%` comment that Obsidian hides
+    pub uid: Option<String>,
+    /// How the block looked when it was last pulled, kept in the same
+    /// comment
+    pub pulled_as: Option<PulledAs>,
+}
+
+/// Hashes of the period and description of a block as it was pulled.
+/// They tell changes of the event apart from edits in the day plan.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PulledAs {
+    pub period: u32,
+    pub desc: u32,
+}
+
+impl PulledAs {
+    pub fn of(block: &Block) -> Self {
+        PulledAs {
+            period: stable_hash(&block.period_str) as u32,
+            desc: stable_hash(&block.desc) as u32,
+        }
+    }
 }
 
 impl Block {
@@ -20,24 +55,46 @@ impl Block {
             period_str: period_str.to_string(),
             origin: origin.to_string(),
             desc: desc.to_string(),
-            duration: duration_of_period(period_str).unwrap_or_else(|| 30),
+            duration: if period_str == ALL_DAY {
+                0
+            } else {
+                duration_of_period(period_str).unwrap_or_else(|| 30)
+            },
             tags: parse_tags(desc),
+            uid: None,
+            pulled_as: None,
         }
     }
 
     pub fn parse_block_string(default_origin: &str, input: &str) -> Result<Self, String> {
         match Regex::new(BLOCKSTRING_REGEX).unwrap().captures(&input) {
             Some(matches) => {
-                let period = matches.get(1).map(|m| m.as_str().to_string());
+                let period = matches.get(1).map(|m| m.as_str().trim_end().to_string());
                 let origin = matches.get(3).map(|m| m.as_str().to_string());
                 let desc = matches.get(4).map(|m| m.as_str().to_string());
 
                 if let Some(desc) = desc {
-                    return Ok(Block::new(
-                        &period.unwrap_or("".to_string()),
-                        &origin.unwrap_or(default_origin.to_string()),
-                        &desc,
-                    ));
+                    let uid_comment = Regex::new(UID_COMMENT_REGEX).unwrap().captures(&desc);
+                    let (desc, uid, pulled_as) = match uid_comment {
+                        Some(c) => (
+                            desc[..c.get(0).unwrap().start()].to_string(),
+                            Some(unescape_uid(&c[1])),
+                            c.get(2).zip(c.get(3)).map(|(period, desc)| PulledAs {
+                                period: u32::from_str_radix(period.as_str(), 16).unwrap(),
+                                desc: u32::from_str_radix(desc.as_str(), 16).unwrap(),
+                            }),
+                        ),
+                        None => (desc, None, None),
+                    };
+                    return Ok(Block {
+                        uid,
+                        pulled_as,
+                        ..Block::new(
+                            &period.unwrap_or("".to_string()),
+                            &origin.unwrap_or(default_origin.to_string()),
+                            &desc,
+                        )
+                    });
                 } else {
                     return Err("Blockstring must have at least a description".to_string());
                 }
@@ -46,16 +103,78 @@ impl Block {
         }
     }
 
+    pub fn is_all_day(&self) -> bool {
+        self.period_str == ALL_DAY
+    }
+
+    /// The start and end time of the block. A block with only a start
+    /// time ends after its default duration. Returns `None` for blocks
+    /// without a period.
+    pub fn times(&self) -> Option<(NaiveTime, NaiveTime)> {
+        let start_str = self.period_str.split('-').next()?.trim();
+        let start_minutes = parse_to_minutes(start_str)?;
+        let start = NaiveTime::from_hms_opt(start_minutes / 60, start_minutes % 60, 0)?;
+        Some((start, start + Duration::minutes(self.duration as i64)))
+    }
+
     pub fn to_block_string(self: &Block, include_origin: bool) -> String {
-        if include_origin {
+        let block_string = if include_origin {
             format!("{} ({}) {}", self.period_str, self.origin, self.desc)
         } else {
             format!("{} {}", self.period_str, self.desc)
+        };
+        match (&self.uid, self.pulled_as) {
+            (Some(uid), Some(pulled_as)) => format!(
+                "{} %%uid:{} {:08x}-{:08x}%%",
+                block_string,
+                escape_uid(uid),
+                pulled_as.period,
+                pulled_as.desc
+            ),
+            (Some(uid), None) => format!("{} %%uid:{}%%", block_string, escape_uid(uid)),
+            _ => block_string,
+        }
+    }
+}
+
+/// Percent-encodes `%` and whitespace, which would end the comment
+fn escape_uid(uid: &str) -> String {
+    let mut escaped = String::new();
+    for c in uid.chars() {
+        if c == '%' || c.is_whitespace() {
+            for byte in c.to_string().bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(c);
         }
     }
+    escaped
 }
 
-fn duration_of_period(period: &str) -> Option<u16> {
+fn unescape_uid(escaped: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = escaped.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+fn duration_of_period(period: &str) -> Option<u32> {
     let parts: Vec<&str> = period.split("-").map(|s| s.trim()).collect();
 
     if parts.len() != 2 {
@@ -73,13 +192,13 @@ fn duration_of_period(period: &str) -> Option<u16> {
     }
 }
 
-fn parse_to_minutes(time_str: &str) -> Option<u16> {
+fn parse_to_minutes(time_str: &str) -> Option<u32> {
     let mut parts = time_str.split(':');
     let hours: u32 = parts.next()?.parse().ok()?;
     let minutes: u32 = parts.next()?.parse().ok()?;
 
     if hours < 24 && minutes < 60 {
-        Some((hours * 60 + minutes).try_into().unwrap())
+        Some(hours * 60 + minutes)
     } else {
         None
     }
@@ -105,6 +224,8 @@ mod tests {
                 desc: "Morning Correspondence".to_string(),
                 duration: 60,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
             }
         );
     }
@@ -122,6 +243,8 @@ mod tests {
                 desc: "Morning Correspondence".to_string(),
                 duration: 30,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
             }
         );
     }
@@ -142,6 +265,8 @@ mod tests {
                 desc: "Morning Correspondence: talk to [[Lars]] later".to_string(),
                 duration: 30,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
             }
         );
     }
@@ -158,6 +283,8 @@ mod tests {
                 desc: "1on1 with Hans".to_string(),
                 duration: 30,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
             }
         );
     }
@@ -174,6 +301,8 @@ mod tests {
                 desc: "Just some text".to_string(),
                 duration: 30,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
             }
         );
     }
@@ -190,8 +319,129 @@ mod tests {
                 desc: "".to_string(),
                 duration: 60,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_times() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+        let b = Block::new("09:15 - 10:45", "Work", "Review");
+        assert_eq!(b.times(), Some((time(9, 15), time(10, 45))));
+
+        let b = Block::new("10:00", "Work", "Review");
+        assert_eq!(b.times(), Some((time(10, 0), time(10, 30))));
+
+        let b = Block::new("", "Work", "Review");
+        assert_eq!(b.times(), None);
+
+        let b = Block::new(ALL_DAY, "Work", "Holiday");
+        assert_eq!(b.times(), None);
+    }
+
+    #[test]
+    fn test_uid_comment() {
+        let b = Block::parse_block_string(
+            "Work",
+            "10:00 - 11:00 (team) Standup @meetings %%uid:abc@google.com/20250310T100000Z%%",
+        )
+        .expect("");
+
+        assert_eq!(b.desc, "Standup @meetings");
+        assert_eq!(b.tags, parse_tags("@meetings"));
+        assert_eq!(b.uid.as_deref(), Some("abc@google.com/20250310T100000Z"));
+        assert_eq!(
+            b.to_block_string(true),
+            "10:00 - 11:00 (team) Standup @meetings %%uid:abc@google.com/20250310T100000Z%%"
+        );
+
+        let b = Block::parse_block_string("Work", "10:00 Notes %%draft%%").expect("");
+        assert_eq!(b.desc, "Notes %%draft%%");
+        assert_eq!(b.uid, None);
+    }
+
+    #[test]
+    fn test_uid_comment_round_trips() {
+        for uid in [
+            "100%-sure",
+            "a%%b",
+            "with space",
+            "%20",
+            "ü%",
+            "abc@google.com",
+        ] {
+            let block = Block {
+                uid: Some(uid.to_string()),
+                ..Block::new("10:00 - 11:00", "team", "Standup")
+            };
+            let pulled = Block {
+                pulled_as: Some(PulledAs::of(&block)),
+                ..block.clone()
+            };
+
+            for block in [block, pulled] {
+                let parsed = Block::parse_block_string("Work", &block.to_block_string(true));
+                assert_eq!(parsed, Ok(block));
+            }
+        }
+
+        let b = Block::parse_block_string(
+            "Work",
+            "10:00 - 11:00 (team) Standup %%uid:100%25-sure 0000abcd-ffff0000%%",
+        )
+        .expect("");
+        assert_eq!(b.desc, "Standup");
+        assert_eq!(b.uid.as_deref(), Some("100%-sure"));
+        assert_eq!(
+            b.pulled_as,
+            Some(PulledAs {
+                period: 0xabcd,
+                desc: 0xffff0000
+            })
+        );
+    }
+
+    #[test]
+    fn test_all_day_block() {
+        let b = Block::parse_block_string("Work", "all day (Team) Easter Monday @pto").expect("");
+
+        assert_eq!(
+            b,
+            Block {
+                period_str: "all day".to_string(),
+                origin: "Team".to_string(),
+                desc: "Easter Monday @pto".to_string(),
+                duration: 0,
+                tags: parse_tags("@pto"),
+                uid: None,
+                pulled_as: None,
             }
         );
+        assert!(b.is_all_day());
+        assert_eq!(b.to_block_string(true), "all day (Team) Easter Monday @pto");
+    }
+
+    #[test]
+    fn test_description_starting_with_all_day() {
+        for input in [
+            "all days off are planned @planning",
+            "all day-trip ideas",
+            "all daylight hours",
+        ] {
+            let b = Block::parse_block_string("Work", input).expect("");
+
+            assert_eq!(b.period_str, "");
+            assert_eq!(b.desc, input);
+            assert!(!b.is_all_day());
+            assert_eq!(b.to_block_string(false).trim_start(), input);
+        }
+
+        let b = Block::parse_block_string("Work", "all day").expect("");
+        assert!(b.is_all_day());
+        assert_eq!(b.desc, "");
     }
 
     #[test]
@@ -206,6 +456,8 @@ mod tests {
                 desc: "Do something".to_string(),
                 duration: 30,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
             }
         );
     }
@@ -224,6 +476,8 @@ mod tests {
                 desc: "A desc with random period from 10:00 - 11:00".to_string(),
                 duration: 30,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
             }
         );
     }
@@ -240,6 +494,8 @@ mod tests {
                 desc: "10:00 - 11:00".to_string(),
                 duration: 60,
                 tags: vec![],
+                uid: None,
+                pulled_as: None,
             }
         );
     }
@@ -252,6 +508,8 @@ mod tests {
             desc: "Buy Coffee".to_string(),
             duration: 60,
             tags: vec![],
+            uid: None,
+            pulled_as: None,
         };
 
         assert_eq!(
@@ -1,6 +1,12 @@
 use serde::Deserialize;
 use std::collections::HashMap;
 
+use crate::billing::TagMetas;
+use crate::cal_day_plan::{EventFormat, Privacy};
+use crate::cal_filter::EventFilter;
+use crate::tag::{TagAliases, TagPattern};
+use crate::time_summary::Allocation;
+
 #[derive(Debug, Deserialize)]
 pub struct Dir {
     pub path: String,
@@ -10,12 +16,94 @@ pub struct Dir {
 #[derive(Debug, Deserialize)]
 pub struct Cal {
     pub uri: String,
+    #[serde(flatten)]
+    pub auth: Auth,
+    /// Extra HTTP headers sent with each request
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Query the URI as a CalDAV collection for the requested days only,
+    /// instead of downloading the whole feed
+    #[serde(default)]
+    pub caldav: bool,
+    /// Minutes a cached copy is used without asking the server again
+    pub max_age: Option<u32>,
+    #[serde(flatten)]
+    pub format: EventFormat,
+    #[serde(flatten)]
+    pub filter: EventFilter,
+}
+
+/// Credentials for a calendar server. With a `username`, the secret is
+/// sent as the password of basic auth, otherwise as a bearer token. The
+/// secret is read from `secret`, the environment variable `secret_env`
+/// or the output of `secret_command`, e.g. `pass show calendar`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Auth {
+    pub username: Option<String>,
+    #[serde(alias = "password")]
+    pub secret: Option<String>,
+    pub secret_env: Option<String>,
+    pub secret_command: Option<String>,
+}
+
+/// A CalDAV collection that `push` writes the time blocks of a directory to
+#[derive(Debug, Deserialize)]
+pub struct Push {
+    /// The URL of the collection, e.g. `http://localhost:5232/me/blocks/`
+    pub url: String,
+    /// The config key of the directory whose blocks are pushed (required
+    /// when multiple dirs are configured)
+    pub origin: Option<String>,
+    #[serde(flatten)]
+    pub auth: Auth,
+    /// How much of each block the events show (default: full)
+    #[serde(default = "default_push_privacy")]
+    pub privacy: Privacy,
+}
+
+fn default_push_privacy() -> Privacy {
+    Privacy::Full
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Tags {
+    /// Blocks with tags matching any of these patterns are not counted
+    /// in reports (default: `["break"]`)
+    #[serde(default = "default_excluded_tags")]
+    pub exclude: Vec<TagPattern>,
+    /// How the time of blocks with several tags is attributed to them
+    #[serde(default)]
+    pub allocation: Allocation,
+    /// Alternative tag spellings and their canonical form, e.g. `mtg = "meetings"`
+    #[serde(default)]
+    pub aliases: TagAliases,
+    /// Billing settings per tag, inherited by its sub-tags
+    #[serde(default)]
+    pub meta: TagMetas,
+}
+
+impl Default for Tags {
+    fn default() -> Self {
+        Tags {
+            exclude: default_excluded_tags(),
+            allocation: Allocation::default(),
+            aliases: TagAliases::default(),
+            meta: TagMetas::default(),
+        }
+    }
+}
+
+fn default_excluded_tags() -> Vec<TagPattern> {
+    vec!["break".parse().unwrap()]
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub dirs: HashMap<String, Dir>,
     pub cals: Option<HashMap<String, Cal>>,
+    #[serde(default)]
+    pub tags: Tags,
+    pub push: Option<Push>,
 }
 
 pub fn load(toml_str: &str) -> Config {
@@ -76,6 +164,167 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_loading_config_excluded_tags() {
+        let toml_str = r#"
+        [dirs]
+        [dirs.work]
+        path = "/home/foo/worknotes"
+        name = "Work"
+
+        [tags]
+        exclude = ["break", "lunch", "private/*"]
+        allocation = "split"
+    "#;
+
+        let cfg = load(toml_str);
+        assert_eq!(cfg.tags.allocation, Allocation::Split);
+        assert_eq!(cfg.tags.exclude.len(), 3);
+        assert_eq!(cfg.tags.exclude[2].levels, vec!["private", "*"]);
+    }
+
+    #[test]
+    fn test_excluded_tags_default_to_break() {
+        let toml_str = r#"
+        [dirs]
+        [dirs.work]
+        path = "/home/foo/worknotes"
+        name = "Work"
+    "#;
+
+        let cfg = load(toml_str);
+        assert_eq!(cfg.tags.exclude.len(), 1);
+        assert_eq!(cfg.tags.exclude[0].levels, vec!["break"]);
+    }
+
+    #[test]
+    fn test_loading_config_tag_aliases() {
+        let toml_str = r#"
+        [dirs]
+        [dirs.work]
+        path = "/tmp/work"
+        name = "Work"
+
+        [tags.aliases]
+        mtg = "meetings"
+        "p/alpha" = "p/[[Project Alpha]]"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let canonical = |input: &str| {
+            config
+                .tags
+                .aliases
+                .canonicalize(&input.parse().unwrap())
+                .map(|t| t.to_string())
+        };
+
+        assert_eq!(canonical("mtg/daily"), Some("@meetings/daily".to_string()));
+        assert_eq!(
+            canonical("p/alpha"),
+            Some("@p/[[Project Alpha]]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_loading_config_tag_meta() {
+        let toml_str = r#"
+        [dirs]
+        [dirs.work]
+        path = "/tmp/work"
+        name = "Work"
+
+        [tags.meta."client/acme"]
+        billable = true
+        rate = 95.5
+        currency = "EUR"
+        rounding = 15
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let meta = config
+            .tags
+            .meta
+            .effective(&["client".to_string(), "acme".to_string()]);
+
+        assert!(meta.is_billable());
+        assert_eq!(meta.rate, Some(95.5));
+        assert_eq!(meta.currency.as_deref(), Some("EUR"));
+        assert_eq!(meta.rounding, Some(15));
+    }
+
+    #[test]
+    fn test_loading_config_cal_with_auth() {
+        let toml_str = r#"
+        [dirs]
+        [dirs.work]
+        path = "/tmp/work"
+        name = "Work"
+
+        [cals.work]
+        uri = "https://dav.example.com/me/work/"
+        caldav = true
+        max_age = 30
+        username = "me"
+        secret_command = "pass show calendar"
+        headers = { "X-Client" = "blockary" }
+        template = "{summary} @meetings"
+        category_tags = true
+        email = "me@example.com"
+        exclude = [{ partstat = "declined" }, { summary = "^Focus time" }]
+
+        [cals.team]
+        uri = "https://calendar.example.com/team.ics"
+        secret_env = "TEAM_CAL_TOKEN"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let cals = config.cals.unwrap();
+
+        let work = &cals["work"];
+        assert!(work.caldav);
+        assert_eq!(work.max_age, Some(30));
+        assert_eq!(work.auth.username.as_deref(), Some("me"));
+        assert_eq!(
+            work.auth.secret_command.as_deref(),
+            Some("pass show calendar")
+        );
+        assert_eq!(work.headers["X-Client"], "blockary");
+        assert_eq!(work.format.template.as_deref(), Some("{summary} @meetings"));
+        assert!(work.format.category_tags);
+        assert_eq!(work.filter.email.as_deref(), Some("me@example.com"));
+        assert_eq!(work.filter.exclude.len(), 2);
+        assert!(work.filter.include.is_empty());
+
+        let team = &cals["team"];
+        assert!(!team.caldav);
+        assert_eq!(team.max_age, None);
+        assert_eq!(team.auth.username, None);
+        assert_eq!(team.auth.secret_env.as_deref(), Some("TEAM_CAL_TOKEN"));
+    }
+
+    #[test]
+    fn test_loading_config_push() {
+        let toml_str = r#"
+        [dirs]
+        [dirs.work]
+        path = "/tmp/work"
+        name = "Work"
+
+        [push]
+        url = "http://localhost:5232/me/blocks/"
+        username = "me"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let push = config.push.unwrap();
+
+        assert_eq!(push.url, "http://localhost:5232/me/blocks/");
+        assert_eq!(push.auth.username.as_deref(), Some("me"));
+        assert_eq!(push.auth.secret, None);
+        assert_eq!(push.privacy, Privacy::Full);
+    }
+
     #[test]
     #[should_panic]
     fn test_loading_config_with_missing_field() {
@@ -0,0 +1,116 @@
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::blockary_cfg::Cal;
+use crate::cal_day_plan::stable_hash;
+
+/// The last response of a calendar, kept to pull without a network and
+/// to ask the server for changes only
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedFeed {
+    pub uri: String,
+    /// Unix time of the last download or confirmation that nothing changed
+    pub fetched_at: i64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub documents: Vec<String>,
+}
+
+impl CachedFeed {
+    /// Whether the copy is younger than `max_age` minutes
+    pub fn is_fresh(&self, max_age: Option<u32>, now: i64) -> bool {
+        max_age.is_some_and(|minutes| now - self.fetched_at < minutes as i64 * 60)
+    }
+
+    pub fn fetched_at_str(&self) -> String {
+        match DateTime::from_timestamp(self.fetched_at, 0) {
+            Some(time) => time
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            None => "an unknown time".to_string(),
+        }
+    }
+}
+
+/// Identifies the cached copy of a calendar. A CalDAV query only returns
+/// the requested days, so those are part of the key.
+pub fn cache_key(cal: &Cal, from_inclusive: &NaiveDate, to_inclusive: &NaiveDate) -> String {
+    let key = if cal.caldav {
+        format!("{}|{}|{}", cal.uri, from_inclusive, to_inclusive)
+    } else {
+        cal.uri.clone()
+    };
+    format!("{:016x}", stable_hash(&key))
+}
+
+pub fn load(key: &str) -> Option<CachedFeed> {
+    fs::read_to_string(cache_file_path(key))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+pub fn save(key: &str, feed: &CachedFeed) -> std::io::Result<()> {
+    let path = cache_file_path(key);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(feed).unwrap())
+}
+
+fn cache_file_path(key: &str) -> PathBuf {
+    let cache_home = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::home_dir().expect("$HOME is not set").join(".cache"),
+    };
+    cache_home
+        .join("blockary")
+        .join("cals")
+        .join(format!("{}.json", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_fresh() {
+        let feed = CachedFeed {
+            uri: "https://calendar.example.com/feed.ics".to_string(),
+            fetched_at: 1_000_000,
+            etag: None,
+            last_modified: None,
+            documents: vec![],
+        };
+
+        assert!(!feed.is_fresh(None, 1_000_000));
+        assert!(feed.is_fresh(Some(30), 1_000_000 + 29 * 60));
+        assert!(!feed.is_fresh(Some(30), 1_000_000 + 30 * 60));
+        assert!(!feed.is_fresh(Some(0), 1_000_000));
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let cal = |caldav: bool| -> Cal {
+            toml::from_str(&format!(
+                "uri = \"https://dav.example.com/me/work/\"\ncaldav = {}",
+                caldav
+            ))
+            .unwrap()
+        };
+        let day = |d| NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+
+        assert_eq!(
+            cache_key(&cal(false), &day(10), &day(10)),
+            cache_key(&cal(false), &day(11), &day(11))
+        );
+        assert_ne!(
+            cache_key(&cal(true), &day(10), &day(10)),
+            cache_key(&cal(true), &day(11), &day(11))
+        );
+        assert_eq!(cache_key(&cal(true), &day(10), &day(10)).len(), 16);
+    }
+}
@@ -1,31 +1,104 @@
 use crate::{
-    block::Block,
+    block::{ALL_DAY, Block},
+    cal_filter::{EventFilter, categories},
     day_plan::{DayPlan, Source},
+    tag::{level_for_name, parse_tags},
 };
-use chrono::{FixedOffset, NaiveDate, NaiveDateTime, Timelike};
-use icalendar::{Calendar, CalendarDateTime, Component, DatePerhapsTime, Event};
+use chrono::{Duration, FixedOffset, NaiveDate, NaiveDateTime, Timelike};
+use clap::ValueEnum;
+use icalendar::{Calendar, CalendarDateTime, Component, DatePerhapsTime, Event, EventLike};
+use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, str::FromStr};
 
-pub fn day_plans_from_ical(ical: &str, origin: &str) -> Vec<DayPlan> {
+/// How much of a block is shown in exported calendar events
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Privacy {
+    /// The full description
+    Full,
+    /// Only the tags, e.g. `@p/alpha @meetings`
+    Tags,
+    /// Just "Busy"
+    #[default]
+    Busy,
+}
+
+/// How the description of a block is built from a calendar event
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct EventFormat {
+    /// A template like `{summary} @meetings {location}`, see
+    /// `event_description` for the placeholders. Without one, the event's
+    /// DESCRIPTION is used.
+    pub template: Option<String>,
+    /// Add the CATEGORIES of an event as tags
+    #[serde(default)]
+    pub category_tags: bool,
+    #[serde(default)]
+    pub all_day: AllDay,
+}
+
+/// What becomes of all-day events like holidays or conferences
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum AllDay {
+    /// Leave them out
+    #[default]
+    Skip,
+    /// An `all day` block on each day of the event
+    Block,
+    /// A block with this period on each day, e.g. `09:00 - 17:00`
+    Window(String),
+}
 
-    let calendar = ical.parse::<Calendar>().unwrap();
+impl TryFrom<String> for AllDay {
+    type Error = String;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        match input.as_str() {
+            "skip" => Ok(AllDay::Skip),
+            "block" => Ok(AllDay::Block),
+            period if period.contains('-') && Block::new(period, "", "").times().is_some() => {
+                Ok(AllDay::Window(period.to_string()))
+            }
+            _ => Err(format!(
+                "all_day must be skip, block or a period like 09:00 - 17:00, not '{}'",
+                input
+            )),
+        }
+    }
+}
+
+/// The day plans of the events in `ical`, or an error if it is not a
+/// valid calendar or uses a time zone that can't be resolved
+pub fn day_plans_from_ical(
+    ical: &str,
+    origin: &str,
+    format: &EventFormat,
+    filter: &EventFilter,
+) -> Result<Vec<DayPlan>, String> {
+    let calendar = ical
+        .parse::<Calendar>()
+        .map_err(|_| "Not a valid calendar".to_string())?;
+    if let Some(tzid) = unknown_tzid(&calendar) {
+        return Err(format!("Unknown time zone '{}'", tzid));
+    }
 
     let single_day_events: Vec<&Event> = calendar
         .components
         .iter()
         .filter_map(|comp| comp.as_event())
         .filter(|event| event_date(event).is_some())
+        .filter(|event| !is_cancelled(event) && filter.accepts(event))
         .collect();
 
     let mut blocks_per_day: HashMap<NaiveDate, Vec<Block>> = HashMap::new();
     for event in single_day_events {
         match extract_period(event) {
             Some(period_str) => {
-                let block = Block::new(
-                    &period_str,
-                    origin,
-                    event.get_description().unwrap_or_else(|| "Busy"),
-                );
+                let block = Block {
+                    uid: event_uid(event),
+                    ..Block::new(&period_str, origin, &event_description(event, format))
+                };
                 blocks_per_day
                     .entry(event_date(event).unwrap())
                     .or_insert(Vec::new())
@@ -35,6 +108,27 @@ pub fn day_plans_from_ical(ical: &str, origin: &str) -> Vec<DayPlan> {
         }
     }
 
+    let period_of_all_day = match &format.all_day {
+        AllDay::Skip => None,
+        AllDay::Block => Some(ALL_DAY),
+        AllDay::Window(period) => Some(period.as_str()),
+    };
+    if let Some(period_str) = period_of_all_day {
+        let all_day_events = calendar
+            .components
+            .iter()
+            .filter_map(|comp| comp.as_event())
+            .filter(|event| !is_cancelled(event) && filter.accepts(event));
+        for event in all_day_events {
+            for day in all_day_dates(event) {
+                blocks_per_day.entry(day).or_default().push(Block {
+                    uid: event_uid(event),
+                    ..Block::new(period_str, origin, &event_description(event, format))
+                });
+            }
+        }
+    }
+
     let mut day_plans = Vec::new();
     for (day, blocks) in blocks_per_day {
         day_plans.push(DayPlan {
@@ -45,38 +139,168 @@ pub fn day_plans_from_ical(ical: &str, origin: &str) -> Vec<DayPlan> {
         });
     }
 
-    day_plans
+    Ok(day_plans)
 }
 
-pub fn day_plan_from_ical(ical: &str, for_day: NaiveDate, origin: &str) -> DayPlan {
-    let day_plans = day_plans_from_ical(ical, origin);
+pub fn day_plan_from_ical(
+    ical: &str,
+    for_day: NaiveDate,
+    origin: &str,
+    format: &EventFormat,
+    filter: &EventFilter,
+) -> Result<DayPlan, String> {
+    let day_plans = day_plans_from_ical(ical, origin, format, filter)?;
 
     for dp in day_plans {
         if dp.day == Some(for_day) {
-            return dp;
+            return Ok(dp);
         }
     }
-    DayPlan {
+    Ok(DayPlan {
         origin: origin.to_string(),
         blocks: Vec::new(),
         day: Some(for_day),
         source: Source::ICalendar,
+    })
+}
+
+/// The description of the block for an event. A template can use
+/// `{summary}`, `{description}`, `{location}`, `{organizer}`,
+/// `{attendees}` (their number) and `{categories}`. An event without a
+/// summary or description is "Busy".
+fn event_description(event: &Event, format: &EventFormat) -> String {
+    let categories: Vec<&str> = categories(event).collect();
+
+    let mut desc = match &format.template {
+        Some(template) => {
+            let organizer = event.properties().get("ORGANIZER").map(|p| {
+                p.get_param_as("CN", |cn| Some(cn.to_string()))
+                    .unwrap_or_else(|| p.value().trim_start_matches("mailto:").to_string())
+            });
+            let attendees = event
+                .multi_properties()
+                .get("ATTENDEE")
+                .map_or(0, |attendees| attendees.len());
+            template
+                .replace("{summary}", event.get_summary().unwrap_or("Busy"))
+                .replace("{description}", event.get_description().unwrap_or(""))
+                .replace("{location}", event.get_location().unwrap_or(""))
+                .replace("{organizer}", organizer.as_deref().unwrap_or(""))
+                .replace("{attendees}", &attendees.to_string())
+                .replace("{categories}", &categories.join(", "))
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ")
+        }
+        None => event.get_description().unwrap_or("").to_string(),
+    };
+
+    if format.category_tags {
+        let existing = parse_tags(&desc);
+        for category in categories {
+            let tag = format!("@{}", level_for_name(category));
+            if !existing
+                .iter()
+                .any(|t| t.tagls == parse_tags(&tag)[0].tagls)
+            {
+                desc = format!("{} {}", desc, tag).trim_start().to_string();
+            }
+        }
+    }
+
+    if desc.is_empty() {
+        "Busy".to_string()
+    } else {
+        desc
     }
 }
 
+/// Renders the original blocks of the day plans as a calendar with one
+/// event per block. Blocks without a time are left out. Blocks sharing
+/// a period on the same day get a `#n` suffix for their UID, like when
+/// pushing.
+pub fn ical_from_day_plans(day_plans: &[DayPlan], privacy: Privacy) -> String {
+    let mut calendar = Calendar::new();
+    calendar.name("Blockary");
+    for plan in day_plans {
+        let Some(day) = plan.day() else { continue };
+        let mut uids: Vec<String> = Vec::new();
+        for block in plan.only_original_blocks_slice() {
+            let mut uid = block_uid(&plan.origin, &day, &block.period_str);
+            let mut n = 1;
+            while uids.contains(&uid) {
+                uid = block_uid(&plan.origin, &day, &format!("{}#{}", block.period_str, n));
+                n += 1;
+            }
+            uids.push(uid.clone());
+            if let Some(event) = block_event(block, &day, &uid, privacy) {
+                calendar.push(event);
+            }
+        }
+    }
+    calendar.done().to_string()
+}
+
+/// The event for a block on `day`, or `None` if the block has no time
+pub fn block_event(block: &Block, day: &NaiveDate, uid: &str, privacy: Privacy) -> Option<Event> {
+    let (start, end) = block.times()?;
+    Some(
+        Event::new()
+            .uid(uid)
+            .summary(&block_summary(block, privacy))
+            .starts(day.and_time(start))
+            .ends(day.and_time(end))
+            .done(),
+    )
+}
+
+/// What is shown of a block's description with the given privacy
+pub fn block_summary(block: &Block, privacy: Privacy) -> String {
+    match privacy {
+        Privacy::Full => block.desc.clone(),
+        Privacy::Tags if !block.tags.is_empty() => block
+            .tags
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<String>>()
+            .join(" "),
+        _ => "Busy".to_string(),
+    }
+}
+
+/// A UID that stays the same across exports as long as the block keeps
+/// its origin, day and period
+pub fn block_uid(origin: &str, day: &NaiveDate, period_str: &str) -> String {
+    let hash = stable_hash(&format!("{}|{}|{}", origin, day, period_str));
+    format!("{:016x}@blockary", hash)
+}
+
+/// FNV-1a, since the hashers of std are not guaranteed to be stable
+/// across releases
+pub fn stable_hash(input: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in input.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
 fn date_perhaps_time_to_naive(dpt: DatePerhapsTime) -> Option<NaiveDateTime> {
     if let DatePerhapsTime::DateTime(cdt) = dpt {
         let naive = match cdt {
             CalendarDateTime::Floating(naive) => naive,
-            CalendarDateTime::Utc(date_time) => date_time
-                .with_timezone(&FixedOffset::from_str("-03:00").unwrap())
-                .naive_local(),
+            CalendarDateTime::Utc(date_time) => {
+                date_time.with_timezone(&local_offset()).naive_local()
+            }
             CalendarDateTime::WithTimezone { date_time, tzid } => {
-                let timezone = FixedOffset::from_str(&tzid).unwrap();
-                date_time
-                    .and_local_timezone(timezone)
-                    .unwrap()
-                    .naive_local()
+                let utc = match time_zone(&tzid)? {
+                    TimeZone::Fixed(offset) => {
+                        date_time.and_local_timezone(offset).earliest()?.to_utc()
+                    }
+                    TimeZone::Named(tz) => date_time.and_local_timezone(tz).earliest()?.to_utc(),
+                };
+                utc.with_timezone(&local_offset()).naive_local()
             }
         };
         return Some(naive);
@@ -85,6 +309,71 @@ fn date_perhaps_time_to_naive(dpt: DatePerhapsTime) -> Option<NaiveDateTime> {
     }
 }
 
+/// The offset that times in UTC or another time zone are converted to
+fn local_offset() -> FixedOffset {
+    FixedOffset::from_str("-03:00").unwrap()
+}
+
+enum TimeZone {
+    Fixed(FixedOffset),
+    Named(chrono_tz::Tz),
+}
+
+/// Resolves a TZID, either a fixed offset like `+01:00` or an IANA name
+/// like `Europe/Berlin`
+fn time_zone(tzid: &str) -> Option<TimeZone> {
+    match FixedOffset::from_str(tzid) {
+        Ok(offset) => Some(TimeZone::Fixed(offset)),
+        Err(_) => tzid.parse().ok().map(TimeZone::Named),
+    }
+}
+
+/// The first TZID of the calendar's events that can't be resolved
+fn unknown_tzid(calendar: &Calendar) -> Option<String> {
+    calendar
+        .components
+        .iter()
+        .filter_map(|comp| comp.as_event())
+        .flat_map(|event| [event.get_start(), event.get_end()])
+        .find_map(|dpt| match dpt {
+            Some(DatePerhapsTime::DateTime(CalendarDateTime::WithTimezone { tzid, .. }))
+                if time_zone(&tzid).is_none() =>
+            {
+                Some(tzid)
+            }
+            _ => None,
+        })
+}
+
+/// Identifies the event across pulls. The instances of a recurring
+/// event share a UID and are told apart by their RECURRENCE-ID.
+fn event_uid(event: &Event) -> Option<String> {
+    let uid = event.get_uid()?;
+    match event.property_value("RECURRENCE-ID") {
+        Some(recurrence_id) => Some(format!("{}/{}", uid, recurrence_id)),
+        None => Some(uid.to_string()),
+    }
+}
+
+fn is_cancelled(event: &Event) -> bool {
+    event
+        .property_value("STATUS")
+        .is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED"))
+}
+
+/// The days of an all-day event, which ends before its DTEND. Events
+/// with a time have none.
+fn all_day_dates(event: &Event) -> Vec<NaiveDate> {
+    let Some(DatePerhapsTime::Date(start)) = event.get_start() else {
+        return vec![];
+    };
+    let end = match event.get_end() {
+        Some(DatePerhapsTime::Date(end)) if end > start => end,
+        _ => start + Duration::days(1),
+    };
+    start.iter_days().take_while(|day| *day < end).collect()
+}
+
 fn event_date(event: &Event) -> Option<NaiveDate> {
     let start = date_perhaps_time_to_naive(event.get_start()?)?;
     let end = date_perhaps_time_to_naive(event.get_end()?)?;
@@ -120,6 +409,287 @@ mod tests {
 
     use super::*;
 
+    fn plan_with_blocks() -> DayPlan {
+        DayPlan {
+            origin: "Work".to_string(),
+            day: NaiveDate::from_ymd_opt(2025, 3, 10),
+            source: Source::ICalendar,
+            blocks: vec![
+                Block::new("09:00 - 10:30", "Work", "Design review @p/alpha @meetings"),
+                Block::new("11:00 - 12:00", "Work", "Dentist"),
+                Block::new("", "Work", "Sometime"),
+                Block::new("13:00 - 14:00", "Personal", "Synced copy"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_ical_from_day_plans_round_trip() {
+        let ical = ical_from_day_plans(&[plan_with_blocks()], Privacy::Full);
+        let calendar = ical.parse::<Calendar>().unwrap();
+        let summaries: Vec<&str> = calendar
+            .components
+            .iter()
+            .filter_map(|c| c.as_event())
+            .filter_map(|e| e.get_summary())
+            .collect();
+
+        assert_eq!(
+            summaries,
+            vec!["Design review @p/alpha @meetings", "Dentist"]
+        );
+        assert!(ical.contains("DTSTART:20250310T090000"));
+        assert!(ical.contains("DTEND:20250310T103000"));
+        assert!(ical.contains(&format!(
+            "UID:{}",
+            block_uid(
+                "Work",
+                &NaiveDate::from_ymd_opt(2025, 3, 10).unwrap(),
+                "09:00 - 10:30"
+            )
+        )));
+    }
+
+    #[test]
+    fn test_ical_privacy() {
+        let summaries = |privacy| {
+            let ical = ical_from_day_plans(&[plan_with_blocks()], privacy);
+            ical.parse::<Calendar>()
+                .unwrap()
+                .components
+                .iter()
+                .filter_map(|c| c.as_event())
+                .filter_map(|e| e.get_summary().map(|s| s.to_string()))
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(summaries(Privacy::Tags), vec!["@p/alpha @meetings", "Busy"]);
+        assert_eq!(summaries(Privacy::Busy), vec!["Busy", "Busy"]);
+    }
+
+    #[test]
+    fn test_blocks_sharing_a_period_get_distinct_uids() {
+        let plan = DayPlan {
+            origin: "Work".to_string(),
+            day: NaiveDate::from_ymd_opt(2025, 3, 10),
+            source: Source::ICalendar,
+            blocks: vec![
+                Block::new("10:00 - 10:30", "Work", "Mail"),
+                Block::new("10:00 - 10:30", "Work", "Chat"),
+                Block::new("10:00 - 10:30", "Work", "Calls"),
+            ],
+        };
+        let ical = ical_from_day_plans(&[plan], Privacy::Full);
+        let uids: Vec<String> = ical
+            .parse::<Calendar>()
+            .unwrap()
+            .components
+            .iter()
+            .filter_map(|c| c.as_event())
+            .filter_map(|e| e.get_uid().map(|u| u.to_string()))
+            .collect();
+
+        let day = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        assert_eq!(
+            uids,
+            vec![
+                block_uid("Work", &day, "10:00 - 10:30"),
+                block_uid("Work", &day, "10:00 - 10:30#1"),
+                block_uid("Work", &day, "10:00 - 10:30#2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_uid_is_stable() {
+        let day = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+
+        assert_eq!(
+            block_uid("Work", &day, "09:00 - 10:30"),
+            block_uid("Work", &day, "09:00 - 10:30")
+        );
+        assert_ne!(
+            block_uid("Work", &day, "09:00 - 10:30"),
+            block_uid("Personal", &day, "09:00 - 10:30")
+        );
+        assert_eq!(block_uid("", &day, "").len(), "@blockary".len() + 16);
+    }
+
+    #[test]
+    fn test_event_description_from_template() {
+        let ical_str = "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VEVENT
+UID:1
+DTSTART:20250310T100000
+DTEND:20250310T110000
+SUMMARY:Design review
+DESCRIPTION:Long agenda
+LOCATION:Room 4
+ORGANIZER;CN=Ada Lovelace:mailto:ada@example.com
+ATTENDEE:mailto:bob@example.com
+ATTENDEE:mailto:eve@example.com
+CATEGORIES:Project Alpha,meetings
+END:VEVENT
+BEGIN:VEVENT
+UID:2
+DTSTART:20250310T120000
+DTEND:20250310T130000
+END:VEVENT
+END:VCALENDAR";
+        let day = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        let descs = |format: EventFormat| -> Vec<String> {
+            let mut blocks =
+                day_plan_from_ical(ical_str, day, "Calendar", &format, &EventFilter::default())
+                    .unwrap()
+                    .blocks;
+            blocks.sort_by(|a, b| a.period_str.cmp(&b.period_str));
+            blocks.into_iter().map(|b| b.desc).collect()
+        };
+
+        assert_eq!(descs(EventFormat::default()), vec!["Long agenda", "Busy"]);
+        assert_eq!(
+            descs(EventFormat {
+                template: Some(
+                    "{summary} @meetings {location} ({organizer}, {attendees}) {categories}"
+                        .to_string()
+                ),
+                category_tags: false,
+                ..EventFormat::default()
+            }),
+            vec![
+                "Design review @meetings Room 4 (Ada Lovelace, 2) Project Alpha, meetings",
+                "Busy @meetings (, 0)",
+            ]
+        );
+        let with_tags = descs(EventFormat {
+            template: Some("{summary} @meetings {location}".to_string()),
+            category_tags: true,
+            ..EventFormat::default()
+        });
+        assert_eq!(
+            with_tags,
+            vec![
+                "Design review @meetings Room 4 @(Project Alpha)",
+                "Busy @meetings"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_all_day_events() {
+        let ical_str = "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VEVENT
+UID:1
+DTSTART;VALUE=DATE:20250310
+DTEND;VALUE=DATE:20250312
+SUMMARY:Conference
+DESCRIPTION:Conference @travel
+END:VEVENT
+BEGIN:VEVENT
+UID:2
+DTSTART;VALUE=DATE:20250312
+SUMMARY:Holiday
+DESCRIPTION:Holiday @pto
+END:VEVENT
+BEGIN:VEVENT
+UID:3
+DTSTART:20250312T100000
+DTEND:20250312T110000
+DESCRIPTION:Standup
+END:VEVENT
+END:VCALENDAR";
+        let blocks_per_day = |all_day: &str| -> Vec<(NaiveDate, String)> {
+            let format = EventFormat {
+                all_day: all_day.to_string().try_into().unwrap(),
+                ..EventFormat::default()
+            };
+            let mut blocks: Vec<(NaiveDate, String)> =
+                day_plans_from_ical(ical_str, "Calendar", &format, &EventFilter::default())
+                    .unwrap()
+                    .into_iter()
+                    .flat_map(|dp| {
+                        let day = dp.day.unwrap();
+                        dp.blocks
+                            .into_iter()
+                            .map(move |b| (day, format!("{} {}", b.period_str, b.desc)))
+                    })
+                    .collect();
+            blocks.sort();
+            blocks
+        };
+        let day = |d| NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+
+        assert_eq!(
+            blocks_per_day("skip"),
+            vec![(day(12), "10:00 - 11:00 Standup".to_string())]
+        );
+        assert_eq!(
+            blocks_per_day("block"),
+            vec![
+                (day(10), "all day Conference @travel".to_string()),
+                (day(11), "all day Conference @travel".to_string()),
+                (day(12), "10:00 - 11:00 Standup".to_string()),
+                (day(12), "all day Holiday @pto".to_string()),
+            ]
+        );
+        assert_eq!(
+            blocks_per_day("09:00 - 17:00")[0],
+            (day(10), "09:00 - 17:00 Conference @travel".to_string())
+        );
+        assert!(AllDay::try_from("sometimes".to_string()).is_err());
+        assert!(AllDay::try_from("09:00".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_event_uids() {
+        let ical_str = "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VEVENT
+UID:weekly@example.com
+RECURRENCE-ID:20250310T100000
+DTSTART:20250310T110000
+DTEND:20250310T113000
+DESCRIPTION:Weekly (moved)
+END:VEVENT
+BEGIN:VEVENT
+UID:once@example.com
+DTSTART:20250310T140000
+DTEND:20250310T150000
+DESCRIPTION:Review
+END:VEVENT
+BEGIN:VEVENT
+UID:cancelled@example.com
+STATUS:CANCELLED
+DTSTART:20250310T160000
+DTEND:20250310T170000
+DESCRIPTION:Retro
+END:VEVENT
+END:VCALENDAR";
+        let day = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+
+        let mut blocks = day_plan_from_ical(
+            ical_str,
+            day,
+            "Calendar",
+            &EventFormat::default(),
+            &EventFilter::default(),
+        )
+        .unwrap()
+        .blocks;
+        blocks.sort_by(|a, b| a.period_str.cmp(&b.period_str));
+
+        let uids: Vec<Option<&str>> = blocks.iter().map(|b| b.uid.as_deref()).collect();
+        assert_eq!(
+            uids,
+            vec![
+                Some("weekly@example.com/20250310T100000"),
+                Some("once@example.com")
+            ]
+        );
+    }
+
     #[test]
     fn test_load_from_valid_icalendar_string() {
         let ical_str = "BEGIN:VCALENDAR
@@ -154,7 +724,14 @@ END:VCALENDAR
 
         let for_day: NaiveDate = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
 
-        let day_plan = day_plan_from_ical(ical_str, for_day, "Calendar");
+        let day_plan = day_plan_from_ical(
+            ical_str,
+            for_day,
+            "Calendar",
+            &EventFormat::default(),
+            &EventFilter::default(),
+        )
+        .unwrap();
         assert_eq!(day_plan.blocks.len(), 1);
         assert_eq!(day_plan.blocks.get(0).unwrap().origin, "Calendar");
         assert_eq!(day_plan.blocks.get(0).unwrap().period_str, "09:00 - 13:00");
@@ -222,7 +799,14 @@ END:VCALENDAR";
 
         let for_day: NaiveDate = NaiveDate::from_ymd_opt(2026, 1, 17).unwrap();
 
-        let day_plan = day_plan_from_ical(ical_str, for_day, "Calendar");
+        let day_plan = day_plan_from_ical(
+            ical_str,
+            for_day,
+            "Calendar",
+            &EventFormat::default(),
+            &EventFilter::default(),
+        )
+        .unwrap();
         assert_eq!(day_plan.blocks.len(), 3);
         assert_eq!(day_plan.blocks.get(0).unwrap().origin, "Calendar");
         assert_eq!(day_plan.blocks.get(0).unwrap().period_str, "10:00 - 10:45");
@@ -434,8 +1018,76 @@ SUMMARY:Busy
 END:VEVENT
 END:VCALENDAR";
 
-        let day_plans = day_plans_from_ical(ical_str, "Calendar");
+        let day_plans = day_plans_from_ical(
+            ical_str,
+            "Calendar",
+            &EventFormat::default(),
+            &EventFilter::default(),
+        )
+        .unwrap();
 
         assert_eq!(day_plans.len(), 16);
     }
+
+    #[test]
+    fn test_invalid_calendar_is_an_error() {
+        let result = day_plans_from_ical(
+            "garbage",
+            "Calendar",
+            &EventFormat::default(),
+            &EventFilter::default(),
+        );
+
+        assert!(result.unwrap_err().starts_with("Not a valid calendar"));
+    }
+
+    /// An event as vdirsyncer stores it, with a TZID for its times
+    const BERLIN_EVENT: &str = "BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//Nextcloud//Calendar//EN
+BEGIN:VTIMEZONE
+TZID:Europe/Berlin
+BEGIN:STANDARD
+DTSTART:19701025T030000
+TZOFFSETFROM:+0200
+TZOFFSETTO:+0100
+END:STANDARD
+END:VTIMEZONE
+BEGIN:VEVENT
+UID:berlin-1
+DTSTAMP:20250301T120000Z
+DTSTART;TZID=Europe/Berlin:20250310T140000
+DTEND;TZID=Europe/Berlin:20250310T150000
+SUMMARY:Call with Berlin
+END:VEVENT
+END:VCALENDAR
+";
+
+    #[test]
+    fn test_times_with_a_time_zone_are_converted() {
+        let day_plans = day_plans_from_ical(
+            BERLIN_EVENT,
+            "Calendar",
+            &EventFormat::default(),
+            &EventFilter::default(),
+        )
+        .unwrap();
+
+        // 14:00 in Berlin is 13:00 UTC, like `DTSTART:20250310T130000Z`
+        assert_eq!(day_plans.len(), 1);
+        assert_eq!(day_plans[0].blocks[0].period_str, "10:00 - 11:00");
+        assert_eq!(day_plans[0].blocks[0].uid.as_deref(), Some("berlin-1"));
+    }
+
+    #[test]
+    fn test_unknown_time_zone_is_an_error() {
+        let result = day_plans_from_ical(
+            &BERLIN_EVENT.replace("TZID=Europe/Berlin", "TZID=Mars/Olympus_Mons"),
+            "Calendar",
+            &EventFormat::default(),
+            &EventFilter::default(),
+        );
+
+        assert_eq!(result.unwrap_err(), "Unknown time zone 'Mars/Olympus_Mons'");
+    }
 }
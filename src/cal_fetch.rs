@@ -0,0 +1,537 @@
+use chrono::{Duration, NaiveDate};
+use icalendar::Calendar;
+use regex::Regex;
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Method, StatusCode};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use walkdir::WalkDir;
+
+use crate::blockary_cfg::{Auth, Cal};
+use crate::cal_cache::CachedFeed;
+
+const CALENDAR_DATA_REGEX: &str =
+    r"(?s)<(?:[\w-]+:)?calendar-data\b[^>]*>(.*?)</(?:[\w-]+:)?calendar-data>";
+
+/// The iCalendar documents of a calendar and the validators the server
+/// sent along, to ask for changes only the next time
+#[derive(Debug, PartialEq)]
+pub struct Fetched {
+    pub documents: Vec<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Fetches the iCalendar documents of a calendar. A plain feed is one
+/// document. A CalDAV collection is queried for the events between the
+/// two days only, and the server answers with one document per event.
+/// A `file://` URI is read from disk, see `read_local`.
+///
+/// With a `cached` copy, the request is conditional and `None` is
+/// returned if the calendar has not changed since.
+pub fn fetch(
+    cal: &Cal,
+    from_inclusive: &NaiveDate,
+    to_inclusive: &NaiveDate,
+    cached: Option<&CachedFeed>,
+) -> Result<Option<Fetched>, String> {
+    if let Some(path) = cal.uri.strip_prefix("file://") {
+        return Ok(Some(Fetched {
+            documents: read_local(Path::new(path))?,
+            etag: None,
+            last_modified: None,
+        }));
+    }
+    let client = Client::new();
+    let request = if cal.caldav {
+        client
+            .request(Method::from_bytes(b"REPORT").unwrap(), &cal.uri)
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(calendar_query(from_inclusive, to_inclusive))
+    } else {
+        client.get(&cal.uri)
+    };
+    let request = cal.headers.iter().fold(request, |request, (name, value)| {
+        request.header(name, value)
+    });
+    let mut request = Credentials::resolve(&cal.auth)?.authorize(request);
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().map_err(|e| e.to_string())?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(format!("server responded {}", response.status()));
+    }
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let body = response.text().map_err(|e| e.to_string())?;
+    Ok(Some(Fetched {
+        documents: if cal.caldav {
+            calendar_data(&body)
+        } else {
+            vec![body]
+        },
+        etag,
+        last_modified,
+    }))
+}
+
+pub fn is_local(cal: &Cal) -> bool {
+    cal.uri.starts_with("file://")
+}
+
+/// Reads a single `.ics` file, or all `.ics` files below a directory like
+/// a vdirsyncer store, which keeps one file per event. Files of a
+/// directory that can't be read or parsed are skipped with a warning.
+fn read_local(path: &Path) -> Result<Vec<String>, String> {
+    if path.is_file() {
+        return read_ics(path).map(|content| vec![content]);
+    }
+    if !path.is_dir() {
+        return Err(format!("{} does not exist", path.display()));
+    }
+    let documents = WalkDir::new(path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "ics")
+        })
+        .filter_map(|entry| match read_ics(entry.path()) {
+            Ok(content) => Some(content),
+            Err(e) => {
+                println!("  Warning: {}. Skipping it.", e);
+                None
+            }
+        })
+        .collect();
+    Ok(documents)
+}
+
+fn read_ics(path: &Path) -> Result<String, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    content
+        .parse::<Calendar>()
+        .map_err(|_| format!("{} is not a valid calendar", path.display()))?;
+    Ok(content)
+}
+
+/// The username and secret of an `Auth`, resolved once so a
+/// `secret_command` runs only once for many requests
+#[derive(Debug, Default)]
+pub struct Credentials {
+    username: Option<String>,
+    secret: Option<String>,
+}
+
+impl Credentials {
+    pub fn resolve(auth: &Auth) -> Result<Self, String> {
+        Ok(Credentials {
+            username: auth.username.clone(),
+            secret: resolve_secret(auth)?,
+        })
+    }
+
+    /// Adds the credentials to the request: basic auth if a username is
+    /// set, otherwise the secret as a bearer token.
+    pub fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match (&self.username, &self.secret) {
+            (Some(username), secret) => request.basic_auth(username, secret.as_ref()),
+            (None, Some(token)) => request.bearer_auth(token),
+            (None, None) => request,
+        }
+    }
+}
+
+fn resolve_secret(auth: &Auth) -> Result<Option<String>, String> {
+    if let Some(secret) = &auth.secret {
+        return Ok(Some(secret.clone()));
+    }
+    if let Some(name) = &auth.secret_env {
+        return std::env::var(name)
+            .map(Some)
+            .map_err(|_| format!("Environment variable {} is not set", name));
+    }
+    if let Some(command) = &auth.secret_command {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .map_err(|e| format!("Could not run '{}': {}", command, e))?;
+        if !output.status.success() {
+            return Err(format!("'{}' failed with {}", command, output.status));
+        }
+        // Like `pass`, the secret is the first line of the output
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        return Ok(Some(stdout.lines().next().unwrap_or("").to_string()));
+    }
+    Ok(None)
+}
+
+/// The body of a CalDAV `calendar-query` for the events between the two
+/// days. The range is padded by a day on both ends since the days are
+/// local and the query is in UTC; events of other days are dropped when
+/// the blocks are created. Recurring events are expanded by the server.
+fn calendar_query(from_inclusive: &NaiveDate, to_inclusive: &NaiveDate) -> String {
+    let format = |day: NaiveDate| day.format("%Y%m%dT000000Z").to_string();
+    let start = format(*from_inclusive - Duration::days(1));
+    let end = format(*to_inclusive + Duration::days(2));
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <D:prop>
+    <C:calendar-data>
+      <C:expand start="{start}" end="{end}"/>
+    </C:calendar-data>
+  </D:prop>
+  <C:filter>
+    <C:comp-filter name="VCALENDAR">
+      <C:comp-filter name="VEVENT">
+        <C:time-range start="{start}" end="{end}"/>
+      </C:comp-filter>
+    </C:comp-filter>
+  </C:filter>
+</C:calendar-query>
+"#
+    )
+}
+
+/// The iCalendar documents in the `calendar-data` elements of a CalDAV
+/// multistatus response
+fn calendar_data(multistatus: &str) -> Vec<String> {
+    Regex::new(CALENDAR_DATA_REGEX)
+        .unwrap()
+        .captures_iter(multistatus)
+        .map(|c| c.get(1).unwrap().as_str().trim())
+        .filter(|data| !data.is_empty())
+        .map(|data| match data.strip_prefix("<![CDATA[") {
+            Some(cdata) => cdata.trim_end_matches("]]>").to_string(),
+            None => unescape_xml(data),
+        })
+        .collect()
+}
+
+/// Decodes the predefined entities and the character references of XML,
+/// e.g. `&amp;`, `&#13;` or `&#xE9;`. Anything else is kept as it is.
+fn unescape_xml(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest
+            .find(';')
+            .and_then(|end| Some((xml_entity(&rest[1..end])?, end)));
+        match decoded {
+            Some((c, end)) => {
+                output.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn xml_entity(name: &str) -> Option<char> {
+    match name {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cal_day_plan::EventFormat;
+    use crate::cal_filter::EventFilter;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread::JoinHandle;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, d).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_secret() {
+        let auth = |toml_str: &str| -> Auth { toml::from_str(toml_str).unwrap() };
+
+        assert_eq!(resolve_secret(&auth("")), Ok(None));
+        assert_eq!(
+            resolve_secret(&auth(r#"password = "inline""#)),
+            Ok(Some("inline".to_string()))
+        );
+        assert_eq!(
+            resolve_secret(&auth(
+                r#"secret_command = "printf 'from-pass\nlogin: me\n'""#
+            )),
+            Ok(Some("from-pass".to_string()))
+        );
+        assert!(resolve_secret(&auth(r#"secret_command = "exit 1""#)).is_err());
+        assert_eq!(
+            resolve_secret(&auth(r#"secret_env = "BLOCKARY_TEST_UNSET_SECRET""#)),
+            Err("Environment variable BLOCKARY_TEST_UNSET_SECRET is not set".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fetch_local_file_and_directory() {
+        let root = std::env::temp_dir().join(format!("blockary-cal-fetch-{}", std::process::id()));
+        let store = root.join("work");
+        fs::create_dir_all(store.join("nested")).unwrap();
+        fs::write(
+            store.join("b.ics"),
+            "BEGIN:VCALENDAR\nX-NAME:b\nEND:VCALENDAR\n",
+        )
+        .unwrap();
+        fs::write(
+            store.join("nested").join("a.ics"),
+            "BEGIN:VCALENDAR\nX-NAME:a\nEND:VCALENDAR\n",
+        )
+        .unwrap();
+        fs::write(store.join("color"), "#ff0000").unwrap();
+        fs::write(store.join("c.ics"), "garbage").unwrap();
+        let from_file = fetch(
+            &cal(format!("file://{}", store.join("b.ics").display())),
+            &day(10),
+            &day(10),
+            None,
+        );
+        let from_dir = fetch(
+            &cal(format!("file://{}", store.display())),
+            &day(10),
+            &day(10),
+            None,
+        );
+        let garbage = fetch(
+            &cal(format!("file://{}", store.join("c.ics").display())),
+            &day(10),
+            &day(10),
+            None,
+        );
+        let missing = fetch(
+            &cal(format!("file://{}", root.join("missing").display())),
+            &day(10),
+            &day(10),
+            None,
+        );
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            from_file.unwrap().unwrap().documents,
+            vec!["BEGIN:VCALENDAR\nX-NAME:b\nEND:VCALENDAR\n"]
+        );
+        assert_eq!(
+            from_dir.unwrap().unwrap().documents,
+            vec![
+                "BEGIN:VCALENDAR\nX-NAME:b\nEND:VCALENDAR\n",
+                "BEGIN:VCALENDAR\nX-NAME:a\nEND:VCALENDAR\n",
+            ]
+        );
+        assert!(
+            garbage
+                .unwrap_err()
+                .contains("c.ics is not a valid calendar")
+        );
+        assert!(missing.unwrap_err().ends_with("missing does not exist"));
+    }
+
+    #[test]
+    fn test_calendar_data() {
+        let multistatus = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/me/work/a.ics</d:href>
+    <d:propstat><d:prop>
+      <cal:calendar-data>BEGIN:VCALENDAR&#13;
+SUMMARY:Design &amp; review&#13;
+END:VCALENDAR</cal:calendar-data>
+    </d:prop></d:propstat>
+  </d:response>
+  <d:response>
+    <d:propstat><d:prop>
+      <calendar-data xmlns="urn:ietf:params:xml:ns:caldav"><![CDATA[BEGIN:VCALENDAR
+SUMMARY:<b>Planning</b>
+END:VCALENDAR]]></calendar-data>
+    </d:prop></d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+        assert_eq!(
+            calendar_data(multistatus),
+            vec![
+                "BEGIN:VCALENDAR\r\nSUMMARY:Design & review\r\nEND:VCALENDAR",
+                "BEGIN:VCALENDAR\nSUMMARY:<b>Planning</b>\nEND:VCALENDAR",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unescape_xml() {
+        assert_eq!(
+            unescape_xml("Caf&#233; &#xE9;&#XE9; &lt;b&gt; &quot;x&apos; &amp;amp;"),
+            "Café éé <b> \"x' &amp;"
+        );
+        assert_eq!(unescape_xml("Line&#13;&#10;&#x9;tab"), "Line\r\n\ttab");
+        assert_eq!(
+            unescape_xml("R&D &unknown; &#xZZ; &#1114112; a & b;"),
+            "R&D &unknown; &#xZZ; &#1114112; a & b;"
+        );
+    }
+
+    fn cal(uri: String) -> Cal {
+        Cal {
+            uri,
+            auth: Auth::default(),
+            headers: HashMap::new(),
+            caldav: false,
+            max_age: None,
+            format: EventFormat::default(),
+            filter: EventFilter::default(),
+        }
+    }
+
+    /// Answers one request on localhost with `response`. Returns the
+    /// address and a handle that joins with the request.
+    fn serve_once(response: String) -> (SocketAddr, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+
+            stream.write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (address, server)
+    }
+
+    #[test]
+    fn test_fetch_caldav_over_localhost() {
+        let body = "<d:multistatus xmlns:d=\"DAV:\"><d:response><d:propstat><d:prop>\
+                    <c:calendar-data xmlns:c=\"urn:ietf:params:xml:ns:caldav\">BEGIN:VCALENDAR\nEND:VCALENDAR\n</c:calendar-data>\
+                    </d:prop></d:propstat></d:response></d:multistatus>";
+        let (address, server) = serve_once(format!(
+            "HTTP/1.1 207 Multi-Status\r\nContent-Type: application/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+
+        let cal = Cal {
+            auth: Auth {
+                username: Some("me".to_string()),
+                secret: Some("secret".to_string()),
+                ..Auth::default()
+            },
+            headers: HashMap::from([("X-Client".to_string(), "blockary".to_string())]),
+            caldav: true,
+            ..cal(format!("http://{}/me/work/", address))
+        };
+
+        let fetched = fetch(&cal, &day(10), &day(10), None).unwrap().unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(fetched.documents, vec!["BEGIN:VCALENDAR\nEND:VCALENDAR"]);
+        assert!(request.starts_with("REPORT /me/work/ HTTP/1.1\r\n"));
+        let request = request.to_lowercase();
+        assert!(request.contains("depth: 1\r\n"));
+        assert!(request.contains("x-client: blockary\r\n"));
+        assert!(request.contains("authorization: basic bwu6c2vjcmv0\r\n"));
+        assert!(
+            request.contains(r#"<c:time-range start="20250309t000000z" end="20250312t000000z"/>"#)
+        );
+    }
+
+    #[test]
+    fn test_fetch_conditionally() {
+        let body = "BEGIN:VCALENDAR\nEND:VCALENDAR\n";
+        let (address, server) = serve_once(format!(
+            "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nLast-Modified: Mon, 10 Mar 2025 08:00:00 GMT\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        let feed = cal(format!("http://{}/feed.ics", address));
+
+        let fetched = fetch(&feed, &day(10), &day(10), None).unwrap();
+        server.join().unwrap();
+        assert_eq!(
+            fetched,
+            Some(Fetched {
+                documents: vec![body.to_string()],
+                etag: Some("\"v2\"".to_string()),
+                last_modified: Some("Mon, 10 Mar 2025 08:00:00 GMT".to_string()),
+            })
+        );
+
+        let (address, server) = serve_once(
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        );
+        let feed = cal(format!("http://{}/feed.ics", address));
+        let cached = CachedFeed {
+            uri: feed.uri.clone(),
+            fetched_at: 0,
+            etag: Some("\"v2\"".to_string()),
+            last_modified: Some("Mon, 10 Mar 2025 08:00:00 GMT".to_string()),
+            documents: vec![body.to_string()],
+        };
+
+        let fetched = fetch(&feed, &day(10), &day(10), Some(&cached)).unwrap();
+        let request = server.join().unwrap().to_lowercase();
+        assert_eq!(fetched, None);
+        assert!(request.contains("if-none-match: \"v2\"\r\n"));
+        assert!(request.contains("if-modified-since: mon, 10 mar 2025 08:00:00 gmt\r\n"));
+    }
+}
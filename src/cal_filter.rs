@@ -0,0 +1,210 @@
+use icalendar::{Component, Event};
+use regex::Regex;
+use serde::Deserialize;
+
+/// Which events of a calendar are pulled. An event is pulled if it
+/// matches one of the `include` rules (or there are none) and none of
+/// the `exclude` rules.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct EventFilter {
+    #[serde(default)]
+    pub include: Vec<EventRule>,
+    #[serde(default)]
+    pub exclude: Vec<EventRule>,
+    /// The calendar user's address, to find their PARTSTAT among the
+    /// attendees of an event
+    pub email: Option<String>,
+}
+
+/// Matches an event if all of the set fields match. Values are compared
+/// case-insensitively.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventRule {
+    /// The calendar user's participation, e.g. `declined` or `tentative`
+    pub partstat: Option<String>,
+    /// e.g. `cancelled` or `tentative`
+    pub status: Option<String>,
+    /// `opaque` or `transparent` (free)
+    pub transp: Option<String>,
+    pub summary: Option<SummaryPattern>,
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SummaryPattern(Regex);
+
+impl TryFrom<String> for SummaryPattern {
+    type Error = String;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        Regex::new(&input)
+            .map(SummaryPattern)
+            .map_err(|e| format!("'{}' is not a valid summary pattern: {}", input, e))
+    }
+}
+
+impl EventFilter {
+    pub fn accepts(&self, event: &Event) -> bool {
+        let matches = |rule: &EventRule| rule.matches(event, self.email.as_deref());
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+impl EventRule {
+    fn matches(&self, event: &Event, email: Option<&str>) -> bool {
+        let same = |expected: &Option<String>, actual: Option<&str>| match expected {
+            Some(expected) => actual.is_some_and(|a| a.eq_ignore_ascii_case(expected)),
+            None => true,
+        };
+        same(&self.partstat, partstat(event, email).as_deref())
+            && same(&self.status, event.property_value("STATUS"))
+            // Events are opaque (busy) unless they say otherwise
+            && same(
+                &self.transp,
+                Some(event.property_value("TRANSP").unwrap_or("OPAQUE")),
+            )
+            && self
+                .summary
+                .as_ref()
+                .is_none_or(|SummaryPattern(re)| re.is_match(event.get_summary().unwrap_or("")))
+            && self
+                .category
+                .as_ref()
+                .is_none_or(|category| categories(event).any(|c| c.eq_ignore_ascii_case(category)))
+    }
+}
+
+pub fn categories(event: &Event) -> impl Iterator<Item = &str> {
+    event
+        .multi_properties()
+        .get("CATEGORIES")
+        .into_iter()
+        .flatten()
+        .flat_map(|p| p.value().split(','))
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+}
+
+/// The PARTSTAT of the attendee with the address `email`. Attendees
+/// without one have not answered yet.
+fn partstat(event: &Event, email: Option<&str>) -> Option<String> {
+    let email = email?;
+    event
+        .multi_properties()
+        .get("ATTENDEE")?
+        .iter()
+        .find(|attendee| {
+            let address = attendee.value().trim_start_matches("mailto:");
+            let param = attendee.get_param_as("EMAIL", |e| Some(e.to_string()));
+            address.eq_ignore_ascii_case(email)
+                || param.is_some_and(|e| e.eq_ignore_ascii_case(email))
+        })
+        .map(|attendee| {
+            attendee
+                .get_param_as("PARTSTAT", |p| Some(p.to_string()))
+                .unwrap_or("NEEDS-ACTION".to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use icalendar::Calendar;
+
+    const ICAL: &str = "BEGIN:VCALENDAR
+VERSION:2.0
+BEGIN:VEVENT
+UID:declined
+SUMMARY:Sales sync
+ATTENDEE;PARTSTAT=DECLINED:mailto:me@example.com
+ATTENDEE;PARTSTAT=ACCEPTED:mailto:bob@example.com
+END:VEVENT
+BEGIN:VEVENT
+UID:accepted
+SUMMARY:Design review
+STATUS:CONFIRMED
+ATTENDEE;PARTSTAT=ACCEPTED:mailto:ME@example.com
+ATTENDEE;PARTSTAT=DECLINED:mailto:bob@example.com
+CATEGORIES:Project Alpha
+END:VEVENT
+BEGIN:VEVENT
+UID:focus
+SUMMARY:Focus time
+TRANSP:TRANSPARENT
+END:VEVENT
+BEGIN:VEVENT
+UID:cancelled
+SUMMARY:Retro
+STATUS:CANCELLED
+CATEGORIES:meetings,OOO
+END:VEVENT
+END:VCALENDAR";
+
+    fn accepted_uids(filter: &str) -> Vec<String> {
+        let filter: EventFilter = toml::from_str(filter).unwrap();
+        let calendar: Calendar = ICAL.parse().unwrap();
+        calendar
+            .components
+            .iter()
+            .filter_map(|c| c.as_event())
+            .filter(|event| filter.accepts(event))
+            .map(|event| event.get_uid().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_exclude() {
+        assert_eq!(
+            accepted_uids(""),
+            vec!["declined", "accepted", "focus", "cancelled"]
+        );
+        assert_eq!(
+            accepted_uids(
+                r#"
+                email = "me@example.com"
+                exclude = [
+                    { partstat = "declined" },
+                    { status = "cancelled" },
+                    { transp = "transparent" },
+                ]
+                "#
+            ),
+            vec!["accepted"]
+        );
+        assert_eq!(
+            accepted_uids(r#"exclude = [{ summary = "^(Focus|Sales)" }, { category = "ooo" }]"#),
+            vec!["accepted"]
+        );
+        // Without an email, nobody's PARTSTAT is known
+        assert_eq!(
+            accepted_uids(r#"exclude = [{ partstat = "declined" }]"#),
+            vec!["declined", "accepted", "focus", "cancelled"]
+        );
+    }
+
+    #[test]
+    fn test_include() {
+        assert_eq!(
+            accepted_uids(
+                r#"
+                include = [{ category = "project alpha" }, { transp = "transparent" }]
+                exclude = [{ summary = "Focus" }]
+                "#
+            ),
+            vec!["accepted"]
+        );
+        assert_eq!(
+            accepted_uids(r#"include = [{ transp = "opaque", status = "cancelled" }]"#),
+            vec!["cancelled"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(toml::from_str::<EventFilter>(r#"exclude = [{ summary = "(" }]"#).is_err());
+        assert!(toml::from_str::<EventFilter>(r#"exclude = [{ colour = "red" }]"#).is_err());
+    }
+}
@@ -1,12 +1,20 @@
 use crate::blockary_cfg;
+use crate::cal_day_plan::Privacy;
+use crate::cmd_export::{self, ExportFormat};
+use crate::cmd_export_ics;
+use crate::cmd_import::{self, ImportFormat};
+use crate::cmd_invoice::{self, InvoiceFormat};
 use crate::cmd_pull;
+use crate::cmd_push;
+use crate::cmd_serve;
 use crate::cmd_spent;
 use crate::cmd_sync;
-use chrono::Datelike;
-use chrono::Duration;
+use crate::cmd_tags;
+use crate::tag::{Tag, TagFilter, TagPattern};
+use crate::time_range::{GroupBy, TimeRange};
+use crate::time_summary::Allocation;
 use chrono::NaiveDate;
-use clap::{Parser, Subcommand, ValueEnum};
-use icalendar::Todo;
+use clap::{Parser, Subcommand};
 use std::env;
 use std::fs;
 
@@ -18,20 +26,6 @@ struct Cli {
     command: Commands,
 }
 
-#[derive(Clone, ValueEnum, Debug)]
-enum TimeRange {
-    /// The current day (default)
-    Today,
-    /// The current week, starts on a Monday
-    ThisWeek,
-    /// The current calendar month
-    ThisMonth,
-    /// The current calendar year
-    ThisYear,
-    /// The calendar week before this one
-    LastWeek,
-}
-
 #[derive(Subcommand)]
 enum Commands {
     /// Sync between local markdown dayplan files
@@ -41,8 +35,123 @@ enum Commands {
     },
     /// Shows how much time was spent on certain things
     Spent {
-        /// Show the time spent for this period
+        /// Show the time spent for this period (default: today). One of today,
+        /// yesterday, this-week, this-month, this-year, last-week, last-month,
+        /// last-year, last-N-days (e.g. last-7-days), YYYY-QN (e.g. 2025-Q1),
+        /// YYYY-WNN (ISO week, e.g. 2025-W11) or YYYY-MM (e.g. 2025-03)
+        #[arg(conflicts_with_all = ["from", "to"])]
+        during: Option<TimeRange>,
+        /// The first day of an explicit period, format: YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// The last day of an explicit period (default: today), format: YYYY-MM-DD
+        #[arg(long, requires = "from")]
+        to: Option<NaiveDate>,
+        /// Break the period down into one column per day, week or month
+        #[arg(short, long)]
+        group_by: Option<GroupBy>,
+        /// Show durations in days of this many hours, e.g. 8 for work days
+        #[arg(long, value_name = "HOURS", value_parser = clap::value_parser!(u32).range(1..=24))]
+        days: Option<u32>,
+        /// Count all-day blocks, e.g. @pto, as this many hours (default: not at all)
+        #[arg(long, value_name = "HOURS", value_parser = clap::value_parser!(u32).range(1..=24))]
+        all_day: Option<u32>,
+        /// Report all directories together, with the origin as the top-level grouping
+        #[arg(short, long)]
+        combined: bool,
+        /// Also exclude blocks with tags matching this pattern, e.g. lunch or private/*
+        #[arg(short = 'x', long, value_name = "PATTERN")]
+        exclude: Vec<TagPattern>,
+        /// Count blocks with tags matching this pattern, even if they are excluded
+        #[arg(short, long, value_name = "PATTERN")]
+        include: Vec<TagPattern>,
+        /// List all blocks without a tag, e.g. to tag them afterwards
+        #[arg(short, long)]
+        untagged: bool,
+        /// How to attribute the time of blocks with several tags (default: from config, or full)
+        #[arg(short, long)]
+        allocation: Option<Allocation>,
+        /// Only count tags matching this pattern, e.g. client/acme or client/*, and
+        /// show them as the top level. Prefix with ! to leave out matching tags
+        #[arg(
+            short,
+            long = "tag",
+            value_name = "PATTERN",
+            allow_hyphen_values = true
+        )]
+        tags: Vec<TagFilter>,
+        /// Show the billable time and amount per tag, as configured in [tags.meta]
+        #[arg(short, long, conflicts_with_all = ["tags", "group_by"])]
+        billing: bool,
+    },
+    /// Export time blocks as CSV for importing them into a time tracking tool
+    Export {
+        /// The period to export (default: today). Accepts the same periods as spent
+        #[arg(conflicts_with_all = ["from", "to"])]
         during: Option<TimeRange>,
+        /// The first day of an explicit period, format: YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// The last day of an explicit period (default: today), format: YYYY-MM-DD
+        #[arg(long, requires = "from")]
+        to: Option<NaiveDate>,
+        #[arg(short, long)]
+        format: ExportFormat,
+        /// Your email (Toggl, Clockify) or full name (Harvest)
+        #[arg(short, long, default_value = "")]
+        user: String,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Export time blocks as an iCalendar file that calendar clients can subscribe to
+    ExportIcs {
+        /// The first day to export (default: today), format: YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// The last day to export (default: same as from), format: YYYY-MM-DD
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Only export blocks of this config directory (default: all)
+        #[arg(long)]
+        origin: Option<String>,
+        /// How much of each block to show
+        #[arg(short, long, default_value = "busy")]
+        privacy: Privacy,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Import time entries from a CSV export of a time tracking tool into the day plans
+    Import {
+        /// The CSV file to import
+        file: String,
+        #[arg(short, long)]
+        format: ImportFormat,
+        /// The config key of the target directory (required when multiple dirs are configured)
+        #[arg(short, long)]
+        target: Option<String>,
+    },
+    /// Export an itemized timesheet of the billable time on a tag
+    Invoice {
+        /// The tag to invoice, including its sub-tags, e.g. client/acme
+        #[arg(short, long)]
+        tag: Tag,
+        /// The period to invoice, e.g. 2025-03 (default: last-month). Accepts
+        /// the same periods as spent
+        #[arg(short, long, conflicts_with_all = ["from", "to"])]
+        month: Option<TimeRange>,
+        /// The first day of an explicit period, format: YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// The last day of an explicit period (default: today), format: YYYY-MM-DD
+        #[arg(long, requires = "from")]
+        to: Option<NaiveDate>,
+        #[arg(short, long, default_value = "csv")]
+        format: InvoiceFormat,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
     },
     /// Pull time blocks from configured calendars into the day plan
     Pull {
@@ -52,6 +161,57 @@ enum Commands {
         /// The config key of the target directory (required when multiple dirs are configured)
         #[arg(short, long)]
         target: Option<String>,
+        /// Use the cached copies of the calendars instead of fetching them
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Push the time blocks of a day to the CalDAV collection configured in [push]
+    Push {
+        /// The date to push (default: today), format: YYYY-MM-DD
+        #[arg(short, long)]
+        date: Option<NaiveDate>,
+        /// Only show what would be changed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Serve the day plans as a live calendar feed and JSON API over HTTP
+    Serve {
+        /// The address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+        /// How much of each block to show in /calendar.ics and /api/days
+        #[arg(long, default_value = "busy")]
+        privacy: Privacy,
+    },
+    /// Lists all tags used in the day plans, or works with them
+    Tags {
+        #[command(subcommand)]
+        command: Option<TagsCommand>,
+        /// Show durations in days of this many hours, e.g. 8 for work days
+        #[arg(long, value_name = "HOURS", value_parser = clap::value_parser!(u32).range(1..=24))]
+        days: Option<u32>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagsCommand {
+    /// Rewrite aliased tags in all day plans to their canonical form
+    Normalize {
+        /// Only show what would be changed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Rename a tag and all of its sub-tags in all day plans
+    Rename {
+        /// The tag to rename, e.g. p/alpha
+        from: Tag,
+        /// The new name, e.g. 'p/[[Project Alpha]]'
+        to: Tag,
+        /// Only show what would be changed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
     },
 }
 
@@ -64,33 +224,134 @@ pub fn run() {
         Commands::Sync { .. } => {
             cmd_sync::command(&config);
         }
-        Commands::Pull { date, target } => {
+        Commands::Pull {
+            date,
+            target,
+            offline,
+        } => {
             let for_day = match date {
                 Some(d) => NaiveDate::parse_from_str(&d, "%Y-%m-%d")
                     .expect("Date must be in YYYY-MM-DD format"),
                 None => today,
             };
-            cmd_pull::command(config, &for_day, target);
+            cmd_pull::command(config, &for_day, target, offline);
         }
-        Commands::Spent { during } => match during {
-            Some(TimeRange::Today) => cmd_spent::command(config, &today, &today),
-            Some(TimeRange::ThisWeek) => {
-                let (start, end) = get_week_bounds(&today);
-                cmd_spent::command(config, &start, &end);
+        Commands::Spent {
+            during,
+            from,
+            to,
+            group_by,
+            days,
+            all_day,
+            combined,
+            exclude,
+            include,
+            untagged,
+            allocation,
+            tags,
+            billing,
+        } => {
+            let (start, end) = match from {
+                Some(from) => (from, to.unwrap_or(today)),
+                None => during.unwrap_or(TimeRange::Today).bounds(&today),
+            };
+            if start > end {
+                eprintln!("Error: The period must not end before {}", start);
+                return;
             }
-            Some(TimeRange::ThisMonth) => {
-                let (start, end) = get_month_bounds(&today);
-                cmd_spent::command(config, &start, &end);
+            let options = cmd_spent::SpentOptions {
+                group_by,
+                hours_per_day: days,
+                all_day_hours: all_day,
+                combined,
+                exclude,
+                include,
+                list_untagged: untagged,
+                allocation: allocation.unwrap_or(config.tags.allocation),
+                tag_filters: tags,
+                billing,
+            };
+            cmd_spent::command(config, &start, &end, &options);
+        }
+        Commands::Export {
+            during,
+            from,
+            to,
+            format,
+            user,
+            output,
+        } => {
+            let (start, end) = match from {
+                Some(from) => (from, to.unwrap_or(today)),
+                None => during.unwrap_or(TimeRange::Today).bounds(&today),
+            };
+            if start > end {
+                eprintln!("Error: The period must not end before {}", start);
+                return;
             }
-            Some(TimeRange::ThisYear) => {
-                let (start, end) = get_year_bounds(&today);
-                cmd_spent::command(config, &start, &end);
+            cmd_export::command(&config, &start, &end, format, &user, output);
+        }
+        Commands::ExportIcs {
+            from,
+            to,
+            origin,
+            privacy,
+            output,
+        } => {
+            let start = from.unwrap_or(today);
+            let end = to.unwrap_or(start);
+            if start > end {
+                eprintln!("Error: The period must not end before {}", start);
+                return;
             }
-            Some(TimeRange::LastWeek) => {
-                let (start, end) = get_week_bounds(&(today - Duration::days(7)));
-                cmd_spent::command(config, &start, &end);
+            cmd_export_ics::command(&config, &start, &end, origin, privacy, output);
+        }
+        Commands::Import {
+            file,
+            format,
+            target,
+        } => {
+            cmd_import::command(&config, &file, format, target);
+        }
+        Commands::Invoice {
+            tag,
+            month,
+            from,
+            to,
+            format,
+            output,
+        } => {
+            let (start, end) = match from {
+                Some(from) => (from, to.unwrap_or(today)),
+                None => month.unwrap_or(TimeRange::LastMonth).bounds(&today),
+            };
+            if start > end {
+                eprintln!("Error: The period must not end before {}", start);
+                return;
+            }
+            cmd_invoice::command(&config, &tag, &start, &end, format, output);
+        }
+        Commands::Push { date, dry_run } => {
+            cmd_push::command(&config, &date.unwrap_or(today), dry_run);
+        }
+        Commands::Serve {
+            bind,
+            port,
+            privacy,
+        } => {
+            let options = cmd_serve::ServeOptions {
+                bind,
+                port,
+                privacy,
+            };
+            cmd_serve::command(&config, &options);
+        }
+        Commands::Tags { command, days } => match command {
+            None => cmd_tags::list(&config, days),
+            Some(TagsCommand::Normalize { dry_run }) => cmd_tags::normalize(&config, dry_run),
+            Some(TagsCommand::Rename { from, to, dry_run }) => {
+                cmd_tags::rename(&config, &from, &to, dry_run)
             }
-            _ => cmd_spent::command(config, &today, &today),
         },
     }
 }
@@ -106,42 +367,3 @@ fn load_configuration() -> blockary_cfg::Config {
     let config = blockary_cfg::load(&config);
     config
 }
-
-fn get_week_bounds(date: &NaiveDate) -> (NaiveDate, NaiveDate) {
-    // .weekday().number_from_monday() returns 1 for Mon, 7 for Sun
-    // Subtracting (1-indexed value - 1) gives us the distance back to Monday
-    let days_from_monday = date.weekday().number_from_monday() - 1;
-    let start_of_week = *date - Duration::days(days_from_monday as i64);
-    let end_of_week = start_of_week + Duration::days(6);
-
-    (start_of_week, end_of_week)
-}
-
-fn get_month_bounds(date: &NaiveDate) -> (NaiveDate, NaiveDate) {
-    let start_of_month = NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap();
-    let next_month_year = if date.month() == 12 {
-        date.year() + 1
-    } else {
-        date.year()
-    };
-    let next_month = if date.month() == 12 {
-        1
-    } else {
-        date.month() + 1
-    };
-
-    let first_of_next_month = NaiveDate::from_ymd_opt(next_month_year, next_month, 1).unwrap();
-    let end_of_month = first_of_next_month - Duration::days(1);
-
-    (start_of_month, end_of_month)
-}
-
-fn get_year_bounds(date: &NaiveDate) -> (NaiveDate, NaiveDate) {
-    let year = date.year();
-
-    // Years always start on Jan 1st and end on Dec 31st
-    let start_of_year = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
-    let end_of_year = NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
-
-    (start_of_year, end_of_year)
-}
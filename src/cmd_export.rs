@@ -0,0 +1,302 @@
+use chrono::{NaiveDate, NaiveTime};
+use clap::ValueEnum;
+use std::fs;
+
+use crate::blockary_cfg;
+use crate::day_plan::{self, DayPlan, DayPlanRepo};
+use crate::tag::{level_name, strip_tags};
+use crate::time_summary::TagExclusions;
+
+/// The time tracking tools whose CSV import format can be exported
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Toggl,
+    Clockify,
+    Harvest,
+}
+
+/// A block as a time entry of a time tracking tool
+#[derive(Debug, Clone, PartialEq)]
+struct TimeEntry {
+    day: NaiveDate,
+    start: NaiveTime,
+    end: NaiveTime,
+    minutes: u32,
+    /// The first level of the first tag
+    project: String,
+    /// The remaining levels of the first tag
+    task: String,
+    /// All other tags
+    tags: Vec<String>,
+    description: String,
+    billable: bool,
+}
+
+pub fn command(
+    config: &blockary_cfg::Config,
+    from_inclusive: &NaiveDate,
+    to_inclusive: &NaiveDate,
+    format: ExportFormat,
+    user: &str,
+    output: Option<String>,
+) {
+    let day_plans: Vec<DayPlan> = config
+        .dirs
+        .values()
+        .flat_map(|dir| {
+            DayPlanRepo {
+                name: dir.name.clone(),
+                repo_type: day_plan::DayPlanRepoType::MarkdownDirectory {
+                    dir: dir.path.clone(),
+                },
+            }
+            .all_between(from_inclusive, to_inclusive)
+        })
+        .collect();
+
+    let (entries, skipped) = time_entries(&day_plans, &config.tags);
+    if skipped > 0 {
+        eprintln!("Skipping {} blocks without a time", skipped);
+    }
+    let document = to_csv(&entries, format, user);
+    match output {
+        Some(path) => match fs::write(&path, document) {
+            Ok(_) => println!("Wrote {} entries to {}", entries.len(), path),
+            Err(e) => eprintln!("Error: Could not write {}: {}", path, e),
+        },
+        None => print!("{}", document),
+    }
+}
+
+/// Converts the original blocks of the plans into time entries, leaving
+/// out excluded tags like `@break`. Also returns the number of blocks
+/// that were skipped because they have no period.
+fn time_entries(day_plans: &[DayPlan], tags: &blockary_cfg::Tags) -> (Vec<TimeEntry>, usize) {
+    let exclusions = TagExclusions {
+        exclude: tags.exclude.clone(),
+        include: vec![],
+    };
+    let mut day_plans: Vec<&DayPlan> = day_plans.iter().filter(|dp| dp.day().is_some()).collect();
+    day_plans.sort_by_key(|dp| dp.day());
+
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for plan in day_plans {
+        let mut blocks = plan.only_original_blocks();
+        blocks.sort_by(|a, b| a.period_str.cmp(&b.period_str));
+        for mut block in blocks {
+            block.tags = tags.aliases.canonicalize_all(&block.tags);
+            if exclusions.excludes(&block) {
+                continue;
+            }
+            let Some((start, end)) = block.times() else {
+                skipped += 1;
+                continue;
+            };
+            let (project, task) = match block.tags.first() {
+                Some(tag) => (
+                    level_name(&tag.tagls[0]).to_string(),
+                    tag.tagls[1..]
+                        .iter()
+                        .map(|l| level_name(l))
+                        .collect::<Vec<&str>>()
+                        .join("/"),
+                ),
+                None => (String::new(), String::new()),
+            };
+            entries.push(TimeEntry {
+                day: plan.day().unwrap(),
+                start,
+                end,
+                minutes: block.duration,
+                project,
+                task,
+                tags: block
+                    .tags
+                    .iter()
+                    .skip(1)
+                    .map(|t| t.tagls.join("/"))
+                    .collect(),
+                description: strip_tags(&block.desc),
+                billable: block
+                    .tags
+                    .iter()
+                    .any(|t| tags.meta.effective(&t.tagls).is_billable()),
+            });
+        }
+    }
+    (entries, skipped)
+}
+
+fn hours(minutes: u32) -> String {
+    format!("{:.2}", minutes as f64 / 60.0)
+}
+
+fn to_csv(entries: &[TimeEntry], format: ExportFormat, user: &str) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let header: &[&str] = match format {
+        ExportFormat::Toggl => &[
+            "Email",
+            "Start date",
+            "Start time",
+            "Duration",
+            "Project",
+            "Task",
+            "Description",
+            "Tags",
+            "Billable",
+        ],
+        ExportFormat::Clockify => &[
+            "Project",
+            "Client",
+            "Description",
+            "Task",
+            "Email",
+            "Tags",
+            "Billable",
+            "Start Date",
+            "Start Time",
+            "End Date",
+            "End Time",
+            "Duration (h)",
+        ],
+        ExportFormat::Harvest => &[
+            "Date",
+            "Client",
+            "Project",
+            "Task",
+            "Notes",
+            "Hours",
+            "First name",
+            "Last name",
+        ],
+    };
+    writer.write_record(header).unwrap();
+
+    for entry in entries {
+        let day = entry.day.format("%Y-%m-%d").to_string();
+        let tags = entry.tags.join(", ");
+        let row: Vec<String> = match format {
+            ExportFormat::Toggl => {
+                let (h, m) = (entry.minutes / 60, entry.minutes % 60);
+                vec![
+                    user.to_string(),
+                    day,
+                    entry.start.format("%H:%M:%S").to_string(),
+                    format!("{:02}:{:02}:00", h, m),
+                    entry.project.clone(),
+                    entry.task.clone(),
+                    entry.description.clone(),
+                    tags,
+                    if entry.billable { "Yes" } else { "No" }.to_string(),
+                ]
+            }
+            ExportFormat::Clockify => vec![
+                entry.project.clone(),
+                String::new(),
+                entry.description.clone(),
+                entry.task.clone(),
+                user.to_string(),
+                tags,
+                if entry.billable { "Yes" } else { "No" }.to_string(),
+                day.clone(),
+                entry.start.format("%H:%M").to_string(),
+                day,
+                entry.end.format("%H:%M").to_string(),
+                hours(entry.minutes),
+            ],
+            ExportFormat::Harvest => {
+                let (first_name, last_name) = user.split_once(' ').unwrap_or((user, ""));
+                vec![
+                    day,
+                    String::new(),
+                    entry.project.clone(),
+                    entry.task.clone(),
+                    entry.description.clone(),
+                    hours(entry.minutes),
+                    first_name.to_string(),
+                    last_name.to_string(),
+                ]
+            }
+        };
+        writer.write_record(row).unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::day_plan::Source;
+
+    fn entries() -> Vec<TimeEntry> {
+        let tags: blockary_cfg::Tags = toml::from_str(
+            r#"
+            [meta."p/[[Project Alpha]]"]
+            billable = true
+            "#,
+        )
+        .unwrap();
+        let plan = DayPlan {
+            origin: "work".to_string(),
+            day: NaiveDate::from_ymd_opt(2025, 3, 10),
+            source: Source::ICalendar,
+            blocks: vec![
+                Block::new(
+                    "09:00 - 10:30",
+                    "work",
+                    "Design review @p/[[Project Alpha]]/(code review) @meetings",
+                ),
+                Block::new("12:00 - 13:00", "work", "Lunch @break"),
+                Block::new("", "work", "No time @p/x"),
+                Block::new("13:00 - 13:15", "home", "Synced copy"),
+            ],
+        };
+
+        let (entries, skipped) = time_entries(&[plan], &tags);
+        assert_eq!(skipped, 1);
+        entries
+    }
+
+    #[test]
+    fn test_time_entries() {
+        let entries = entries();
+
+        assert_eq!(
+            entries,
+            vec![TimeEntry {
+                day: NaiveDate::from_ymd_opt(2025, 3, 10).unwrap(),
+                start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(10, 30, 0).unwrap(),
+                minutes: 90,
+                project: "p".to_string(),
+                task: "Project Alpha/code review".to_string(),
+                tags: vec!["meetings".to_string()],
+                description: "Design review".to_string(),
+                billable: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_csv_formats() {
+        let entries = entries();
+
+        assert_eq!(
+            to_csv(&entries, ExportFormat::Toggl, "me@example.com"),
+            "Email,Start date,Start time,Duration,Project,Task,Description,Tags,Billable\n\
+             me@example.com,2025-03-10,09:00:00,01:30:00,p,Project Alpha/code review,Design review,meetings,Yes\n"
+        );
+        assert_eq!(
+            to_csv(&entries, ExportFormat::Clockify, "me@example.com"),
+            "Project,Client,Description,Task,Email,Tags,Billable,Start Date,Start Time,End Date,End Time,Duration (h)\n\
+             p,,Design review,Project Alpha/code review,me@example.com,meetings,Yes,2025-03-10,09:00,2025-03-10,10:30,1.50\n"
+        );
+        assert_eq!(
+            to_csv(&entries, ExportFormat::Harvest, "Ada Lovelace"),
+            "Date,Client,Project,Task,Notes,Hours,First name,Last name\n\
+             2025-03-10,,p,Project Alpha/code review,Design review,1.50,Ada,Lovelace\n"
+        );
+    }
+}
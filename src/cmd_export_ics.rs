@@ -0,0 +1,51 @@
+use chrono::NaiveDate;
+use std::fs;
+
+use crate::blockary_cfg::Config;
+use crate::cal_day_plan::{Privacy, ical_from_day_plans};
+use crate::day_plan::{DayPlan, DayPlanRepo, DayPlanRepoType};
+
+pub fn command(
+    config: &Config,
+    from_inclusive: &NaiveDate,
+    to_inclusive: &NaiveDate,
+    origin: Option<String>,
+    privacy: Privacy,
+    output: Option<String>,
+) {
+    if let Some(key) = &origin
+        && !config.dirs.contains_key(key)
+    {
+        let available: Vec<&str> = config.dirs.keys().map(|k| k.as_str()).collect();
+        eprintln!(
+            "Error: Unknown origin '{}'. Available: {}",
+            key,
+            available.join(", ")
+        );
+        return;
+    }
+
+    let day_plans: Vec<DayPlan> = config
+        .dirs
+        .iter()
+        .filter(|(key, _)| origin.as_ref().is_none_or(|o| o == *key))
+        .flat_map(|(_, dir)| {
+            DayPlanRepo {
+                name: dir.name.clone(),
+                repo_type: DayPlanRepoType::MarkdownDirectory {
+                    dir: dir.path.clone(),
+                },
+            }
+            .all_between(from_inclusive, to_inclusive)
+        })
+        .collect();
+
+    let ical = ical_from_day_plans(&day_plans, privacy);
+    match output {
+        Some(path) => match fs::write(&path, ical) {
+            Ok(_) => println!("Wrote {} day plans to {}", day_plans.len(), path),
+            Err(e) => eprintln!("Error: Could not write {}: {}", path, e),
+        },
+        None => print!("{}", ical),
+    }
+}
@@ -0,0 +1,249 @@
+use chrono::{NaiveDate, NaiveTime};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+
+use crate::block::Block;
+use crate::blockary_cfg::Config;
+use crate::cmd_pull::{merge_blocks, resolve_target_dir};
+use crate::day_plan::{DayPlanRepo, DayPlanRepoType};
+use crate::tag::level_for_name;
+
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y"];
+const TIME_FORMATS: [&str; 4] = ["%H:%M:%S", "%H:%M", "%I:%M:%S %p", "%I:%M %p"];
+
+/// The time tracking tools whose CSV exports can be imported
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    Toggl,
+    Clockify,
+}
+
+/// The names of the CSV columns used by the import
+struct Columns {
+    start_date: &'static str,
+    start_time: &'static str,
+    end_date: &'static str,
+    end_time: &'static str,
+    client: &'static str,
+    project: &'static str,
+    description: &'static str,
+    tags: &'static str,
+}
+
+impl ImportFormat {
+    fn columns(&self) -> Columns {
+        match self {
+            ImportFormat::Toggl => Columns {
+                start_date: "Start date",
+                start_time: "Start time",
+                end_date: "End date",
+                end_time: "End time",
+                client: "Client",
+                project: "Project",
+                description: "Description",
+                tags: "Tags",
+            },
+            ImportFormat::Clockify => Columns {
+                start_date: "Start Date",
+                start_time: "Start Time",
+                end_date: "End Date",
+                end_time: "End Time",
+                client: "Client",
+                project: "Project",
+                description: "Description",
+                tags: "Tags",
+            },
+        }
+    }
+}
+
+pub fn command(config: &Config, file: &str, format: ImportFormat, target: Option<String>) {
+    let target_dir = match resolve_target_dir(config, target.as_deref()) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let content = match std::fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error: Could not read {}: {}", file, e);
+            return;
+        }
+    };
+    let blocks_per_day = match blocks_from_csv(&content, format, &target_dir.name) {
+        Ok(blocks_per_day) => blocks_per_day,
+        Err(e) => {
+            eprintln!("Error: Could not import {}: {}", file, e);
+            return;
+        }
+    };
+
+    let repo = DayPlanRepo {
+        name: target_dir.name.clone(),
+        repo_type: DayPlanRepoType::MarkdownDirectory {
+            dir: target_dir.path.clone(),
+        },
+    };
+    for (day, blocks) in blocks_per_day {
+        let Some(mut plan) = repo.all_of_day(&day).into_iter().next() else {
+            println!(
+                "Warning: No file found for {} in '{}' ({}). Skipping {} block(s).",
+                day,
+                target_dir.name,
+                target_dir.path,
+                blocks.len()
+            );
+            continue;
+        };
+        let new_blocks_added = merge_blocks(&mut plan, &blocks, file);
+        if new_blocks_added > 0 {
+            plan.blocks.sort_by(|a, b| a.period_str.cmp(&b.period_str));
+            plan.write_to_daily_file();
+            println!("Added {} block(s) to {}.", new_blocks_added, day);
+        }
+    }
+}
+
+/// Converts the time entries of a CSV export into blocks, grouped by day.
+/// The client and project become a tag like `@client/project`, and the
+/// tags of an entry are added as tags as well. Entries that run past
+/// midnight end at 23:59.
+fn blocks_from_csv(
+    content: &str,
+    format: ImportFormat,
+    origin: &str,
+) -> Result<BTreeMap<NaiveDate, Vec<Block>>, String> {
+    let columns = format.columns();
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let index_of = |name: &str| headers.iter().position(|h| h.trim() == name);
+    let required = |name: &str| index_of(name).ok_or(format!("Missing column '{}'", name));
+
+    let start_date = required(columns.start_date)?;
+    let start_time = required(columns.start_time)?;
+    let end_time = required(columns.end_time)?;
+    let end_date = index_of(columns.end_date);
+    let client = index_of(columns.client);
+    let project = index_of(columns.project);
+    let description = index_of(columns.description);
+    let tags = index_of(columns.tags);
+
+    let mut blocks_per_day: BTreeMap<NaiveDate, Vec<Block>> = BTreeMap::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        let field = |index: Option<usize>| index.and_then(|i| record.get(i)).unwrap_or("").trim();
+        let row_error =
+            |what: &str, value: &str| format!("Row {}: invalid {} '{}'", line + 2, what, value);
+
+        let day = parse_date(field(Some(start_date)))
+            .ok_or_else(|| row_error("date", field(Some(start_date))))?;
+        let start = parse_time(field(Some(start_time)))
+            .ok_or_else(|| row_error("time", field(Some(start_time))))?;
+        let mut end = parse_time(field(Some(end_time)))
+            .ok_or_else(|| row_error("time", field(Some(end_time))))?;
+        let ends_on_later_day = parse_date(field(end_date)).is_some_and(|d| d > day);
+        if ends_on_later_day || end < start {
+            end = NaiveTime::from_hms_opt(23, 59, 0).unwrap();
+        }
+
+        let mut desc_parts = vec![field(description).to_string()];
+        let levels: Vec<String> = [field(client), field(project)]
+            .iter()
+            .filter(|name| !name.is_empty())
+            .map(|name| level_for_name(name))
+            .collect();
+        if !levels.is_empty() {
+            desc_parts.push(format!("@{}", levels.join("/")));
+        }
+        for tag in field(tags).split(',').filter(|t| !t.trim().is_empty()) {
+            desc_parts.push(format!("@{}", level_for_name(tag)));
+        }
+        let desc = desc_parts
+            .iter()
+            .filter(|p| !p.is_empty())
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let period = format!("{} - {}", start.format("%H:%M"), end.format("%H:%M"));
+        blocks_per_day
+            .entry(day)
+            .or_default()
+            .push(Block::new(&period, origin, &desc));
+    }
+    Ok(blocks_per_day)
+}
+
+fn parse_date(input: &str) -> Option<NaiveDate> {
+    DATE_FORMATS
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(input, f).ok())
+}
+
+fn parse_time(input: &str) -> Option<NaiveTime> {
+    TIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveTime::parse_from_str(input, f).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_from_toggl_csv() {
+        let csv = "User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags\n\
+                   Ada,ada@example.com,Acme,Project Alpha,,Design review,Yes,2025-03-10,09:00:00,2025-03-10,10:30:00,01:30:00,\"meetings, deep work\"\n\
+                   Ada,ada@example.com,,,,Late deploy,No,2025-03-10,23:00:00,2025-03-11,01:00:00,02:00:00,\n\
+                   Ada,ada@example.com,,Admin,,,No,2025-03-11,08:00:00,2025-03-11,08:15:00,00:15:00,\n";
+
+        let blocks = blocks_from_csv(csv, ImportFormat::Toggl, "Work").unwrap();
+
+        let day = |d| NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+        assert_eq!(
+            blocks[&day(10)],
+            vec![
+                Block::new(
+                    "09:00 - 10:30",
+                    "Work",
+                    "Design review @Acme/(Project Alpha) @meetings @(deep work)"
+                ),
+                Block::new("23:00 - 23:59", "Work", "Late deploy"),
+            ]
+        );
+        assert_eq!(
+            blocks[&day(11)],
+            vec![Block::new("08:00 - 08:15", "Work", "@Admin")]
+        );
+        assert_eq!(
+            blocks[&day(10)][0].tags[0].tagls,
+            vec!["Acme", "(Project Alpha)"]
+        );
+    }
+
+    #[test]
+    fn test_blocks_from_clockify_csv() {
+        let csv = "Project,Client,Description,Task,User,Email,Tags,Billable,Start Date,Start Time,End Date,End Time,Duration (h)\n\
+                   Website,Globex,Fix header,,Ada,ada@example.com,,Yes,03/12/2025,02:00:00 PM,03/12/2025,03:00:00 PM,1.00\n";
+
+        let blocks = blocks_from_csv(csv, ImportFormat::Clockify, "Work").unwrap();
+
+        assert_eq!(
+            blocks[&NaiveDate::from_ymd_opt(2025, 3, 12).unwrap()],
+            vec![Block::new(
+                "14:00 - 15:00",
+                "Work",
+                "Fix header @Globex/Website"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let result = blocks_from_csv("Project,Description\nA,B\n", ImportFormat::Toggl, "Work");
+
+        assert_eq!(result, Err("Missing column 'Start date'".to_string()));
+    }
+}
@@ -0,0 +1,387 @@
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use std::fs;
+
+use crate::blockary_cfg;
+use crate::day_plan::{self, DayPlan, DayPlanRepo};
+use crate::tag::Tag;
+use crate::time_summary::{TagExclusions, format_duration};
+
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq, Eq)]
+pub enum InvoiceFormat {
+    #[default]
+    Csv,
+    Markdown,
+}
+
+/// One billed block of the timesheet, or the rounding of a tag's total
+#[derive(Debug, Clone, PartialEq)]
+struct InvoiceItem {
+    day: NaiveDate,
+    period: String,
+    desc: String,
+    billed_minutes: u32,
+    rate: Option<f64>,
+    currency: String,
+    amount: Option<f64>,
+}
+
+pub fn command(
+    config: &blockary_cfg::Config,
+    tag: &Tag,
+    from_inclusive: &NaiveDate,
+    to_inclusive: &NaiveDate,
+    format: InvoiceFormat,
+    output: Option<String>,
+) {
+    let day_plans: Vec<DayPlan> = config
+        .dirs
+        .values()
+        .flat_map(|dir| {
+            DayPlanRepo {
+                name: dir.name.clone(),
+                repo_type: day_plan::DayPlanRepoType::MarkdownDirectory {
+                    dir: dir.path.clone(),
+                },
+            }
+            .all_between(from_inclusive, to_inclusive)
+        })
+        .collect();
+
+    let items = invoice_items(&day_plans, tag, &config.tags);
+    if items.is_empty() {
+        eprintln!(
+            "Error: No billable time for {} between {} and {}",
+            tag, from_inclusive, to_inclusive
+        );
+        return;
+    }
+
+    let document = match format {
+        InvoiceFormat::Csv => to_csv(&items),
+        InvoiceFormat::Markdown => to_markdown(&items, tag, from_inclusive, to_inclusive),
+    };
+    match output {
+        Some(path) => match fs::write(&path, document) {
+            Ok(_) => println!("Wrote {} items to {}", items.len(), path),
+            Err(e) => eprintln!("Error: Could not write {}: {}", path, e),
+        },
+        None => print!("{}", document),
+    }
+}
+
+/// Collects the original blocks tagged with `tag` or one of its
+/// sub-tags, as far as they are billable and not excluded. Like `spent
+/// --billing`, the rounding increment applies to the total of each
+/// configured tag, which is added as one rounding item per tag after the
+/// blocks.
+fn invoice_items(day_plans: &[DayPlan], tag: &Tag, tags: &blockary_cfg::Tags) -> Vec<InvoiceItem> {
+    let exclusions = TagExclusions {
+        exclude: tags.exclude.clone(),
+        include: vec![],
+    };
+    let mut day_plans: Vec<&DayPlan> = day_plans.iter().filter(|dp| dp.day().is_some()).collect();
+    day_plans.sort_by_key(|dp| dp.day());
+
+    let mut items = Vec::new();
+    // Unrounded minutes and the last day per configured tag
+    let mut accounts: Vec<(Vec<String>, u32, NaiveDate)> = Vec::new();
+    for plan in day_plans {
+        let mut blocks = plan.only_original_blocks();
+        blocks.sort_by(|a, b| a.period_str.cmp(&b.period_str));
+        for mut block in blocks {
+            block.tags = tags.aliases.canonicalize_all(&block.tags);
+            if exclusions.excludes(&block) {
+                continue;
+            }
+            let minutes_per_tag = tags.allocation.minutes_per_tag(&block);
+            let matching: Vec<(&Tag, u32)> = block
+                .tags
+                .iter()
+                .zip(minutes_per_tag)
+                .filter(|(t, _)| t.tagls.starts_with(&tag.tagls))
+                .collect();
+            let Some((first_match, _)) = matching.first() else {
+                continue;
+            };
+            let meta = tags.meta.effective(&first_match.tagls);
+            let minutes = matching
+                .iter()
+                .map(|(_, m)| m)
+                .sum::<u32>()
+                .min(block.duration);
+            if !meta.is_billable() || minutes == 0 {
+                continue;
+            }
+
+            let day = plan.day().unwrap();
+            let account = tags
+                .meta
+                .account_of(&first_match.tagls)
+                .unwrap_or(&first_match.tagls)
+                .to_vec();
+            match accounts.iter_mut().find(|(a, _, _)| *a == account) {
+                Some((_, total, last_day)) => {
+                    *total += minutes;
+                    *last_day = day;
+                }
+                None => accounts.push((account, minutes, day)),
+            }
+            items.push(InvoiceItem {
+                day,
+                period: block.period_str.clone(),
+                desc: block.desc.clone(),
+                billed_minutes: minutes,
+                rate: meta.rate,
+                amount: meta.amount(minutes),
+                currency: meta.currency.unwrap_or_default(),
+            });
+        }
+    }
+
+    for (account, minutes, last_day) in accounts {
+        let meta = tags.meta.effective(&account);
+        let rounding = meta.billed_minutes(minutes) - minutes;
+        if rounding == 0 {
+            continue;
+        }
+        items.push(InvoiceItem {
+            day: last_day,
+            period: String::new(),
+            desc: format!(
+                "Rounding @{} up to {} minutes",
+                account.join("/"),
+                meta.rounding.unwrap_or_default()
+            ),
+            billed_minutes: rounding,
+            rate: meta.rate,
+            amount: meta.amount(rounding),
+            currency: meta.currency.unwrap_or_default(),
+        });
+    }
+    items
+}
+
+fn format_money(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.2}", v)).unwrap_or_default()
+}
+
+fn to_csv(items: &[InvoiceItem]) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "Date",
+            "Period",
+            "Description",
+            "Duration",
+            "Rate",
+            "Amount",
+            "Currency",
+        ])
+        .unwrap();
+    for item in items {
+        writer
+            .write_record([
+                item.day.to_string(),
+                item.period.clone(),
+                item.desc.clone(),
+                format_duration(item.billed_minutes, None),
+                format_money(item.rate),
+                format_money(item.amount),
+                item.currency.clone(),
+            ])
+            .unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+fn to_markdown(
+    items: &[InvoiceItem],
+    tag: &Tag,
+    from_inclusive: &NaiveDate,
+    to_inclusive: &NaiveDate,
+) -> String {
+    let mut lines = vec![
+        format!("# Timesheet {}", tag),
+        String::new(),
+        format!("{} – {}", from_inclusive, to_inclusive),
+        String::new(),
+        "| Date | Period | Description | Duration | Rate | Amount |".to_string(),
+        "| --- | --- | --- | ---: | ---: | ---: |".to_string(),
+    ];
+    for item in items {
+        let with_currency = |value: Option<f64>| match value {
+            Some(v) => format!("{:.2} {}", v, item.currency).trim_end().to_string(),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "| {} | {} | {} | {} | {} | {} |",
+            item.day,
+            item.period,
+            item.desc.replace('|', "\\|"),
+            format_duration(item.billed_minutes, None),
+            with_currency(item.rate),
+            with_currency(item.amount),
+        ));
+    }
+
+    let mut currencies: Vec<&str> = items.iter().map(|i| i.currency.as_str()).collect();
+    currencies.sort();
+    currencies.dedup();
+    for currency in currencies {
+        let in_currency = items.iter().filter(|i| i.currency == currency);
+        let minutes: u32 = in_currency.clone().map(|i| i.billed_minutes).sum();
+        let amount: f64 = in_currency.filter_map(|i| i.amount).sum();
+        lines.push(format!(
+            "| **Total** | | | **{}** | | **{}** |",
+            format_duration(minutes, None),
+            format!("{:.2} {}", amount, currency).trim_end()
+        ));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing;
+    use crate::block::Block;
+    use crate::day_plan::Source;
+    use crate::time_summary::time_per_tag;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, d).unwrap()
+    }
+
+    fn tags_config() -> blockary_cfg::Tags {
+        toml::from_str(
+            r#"
+            [aliases]
+            acme = "client/acme"
+
+            [meta."client/acme"]
+            billable = true
+            rate = 100.0
+            currency = "EUR"
+            rounding = 15
+
+            [meta."client/acme/internal"]
+            billable = false
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn plans() -> Vec<DayPlan> {
+        vec![
+            DayPlan {
+                origin: "work".to_string(),
+                day: Some(day(11)),
+                source: Source::ICalendar,
+                blocks: vec![
+                    Block::new("10:00 - 10:50", "work", "Review | merge @client/acme/dev"),
+                    Block::new("09:00 - 09:20", "work", "Sync @acme"),
+                    Block::new("11:00 - 12:00", "work", "Planning @client/acme/internal"),
+                    Block::new("12:00 - 13:00", "work", "@client/globex"),
+                    Block::new("13:00 - 14:00", "home", "Synced copy @client/acme"),
+                    Block::new("14:00 - 14:30", "work", "Lunch @client/acme @break"),
+                ],
+            },
+            DayPlan {
+                origin: "work".to_string(),
+                day: Some(day(10)),
+                source: Source::ICalendar,
+                blocks: vec![Block::new("09:00 - 10:00", "work", "Kickoff @client/acme")],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_invoice_items() {
+        let tag: Tag = "client/acme".parse().unwrap();
+
+        let items = invoice_items(&plans(), &tag, &tags_config());
+
+        let summary: Vec<(NaiveDate, &str, u32, String)> = items
+            .iter()
+            .map(|i| {
+                (
+                    i.day,
+                    i.period.as_str(),
+                    i.billed_minutes,
+                    format_money(i.amount),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (day(10), "09:00 - 10:00", 60, "100.00".to_string()),
+                (day(11), "09:00 - 09:20", 20, "33.33".to_string()),
+                (day(11), "10:00 - 10:50", 50, "83.33".to_string()),
+                (day(11), "", 5, "8.33".to_string()),
+            ]
+        );
+        assert_eq!(items[3].desc, "Rounding @client/acme up to 15 minutes");
+    }
+
+    #[test]
+    fn test_invoice_rounds_like_spent_billing() {
+        let tag: Tag = "client/acme".parse().unwrap();
+        let tags = tags_config();
+        let exclusions = TagExclusions {
+            exclude: tags.exclude.clone(),
+            include: vec![],
+        };
+        let blocks: Vec<Block> = plans()
+            .iter()
+            .flat_map(|plan| plan.only_original_blocks())
+            .map(|mut block| {
+                block.tags = tags.aliases.canonicalize_all(&block.tags);
+                block
+            })
+            .filter(|block| !exclusions.excludes(block))
+            .collect();
+        let tag_timings = time_per_tag(
+            &blocks.iter().collect::<Vec<&Block>>(),
+            tags.allocation,
+            &[],
+        );
+
+        let lines = billing::billing_lines(&tag_timings, &tags.meta);
+        let items = invoice_items(&plans(), &tag, &tags);
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].billed_minutes, 135);
+        assert_eq!(
+            items.iter().map(|i| i.billed_minutes).sum::<u32>(),
+            lines[0].billed_minutes
+        );
+        assert_eq!(
+            format_money(Some(items.iter().filter_map(|i| i.amount).sum())),
+            format_money(lines[0].amount)
+        );
+    }
+
+    #[test]
+    fn test_csv_and_markdown() {
+        let tag: Tag = "client/acme".parse().unwrap();
+        let items = invoice_items(&plans(), &tag, &tags_config());
+
+        let csv = to_csv(&items[2..]);
+        assert_eq!(
+            csv,
+            "Date,Period,Description,Duration,Rate,Amount,Currency\n\
+             2025-03-11,10:00 - 10:50,Review | merge @client/acme/dev,00:50,100.00,83.33,EUR\n\
+             2025-03-11,,Rounding @client/acme up to 15 minutes,00:05,100.00,8.33,EUR\n"
+        );
+
+        let markdown = to_markdown(&items, &tag, &day(1), &day(31));
+        assert!(markdown.starts_with("# Timesheet @client/acme\n\n2025-03-01 – 2025-03-31\n"));
+        assert!(markdown.contains(
+            "| 2025-03-11 | 10:00 - 10:50 | Review \\| merge @client/acme/dev | 00:50 | 100.00 EUR | 83.33 EUR |"
+        ));
+        assert!(markdown.contains("| **Total** | | | **02:15** | | **225.00 EUR** |"));
+    }
+}
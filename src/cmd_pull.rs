@@ -1,12 +1,15 @@
-use chrono::NaiveDate;
+use chrono::{NaiveDate, Utc};
 
 use crate::{
-    blockary_cfg::{Config, Dir},
+    block::{Block, PulledAs},
+    blockary_cfg::{Cal, Config, Dir},
+    cal_cache::{self, CachedFeed},
     cal_day_plan::day_plan_from_ical,
-    day_plan::{DayPlanRepo, DayPlanRepoType},
+    cal_fetch::{fetch, is_local},
+    day_plan::{DayPlan, DayPlanRepo, DayPlanRepoType},
 };
 
-pub fn command(config: Config, for_day: &NaiveDate, target: Option<String>) {
+pub fn command(config: Config, for_day: &NaiveDate, target: Option<String>, offline: bool) {
     let cals = match &config.cals {
         Some(cals) if !cals.is_empty() => cals,
         _ => {
@@ -45,47 +48,40 @@ pub fn command(config: Config, for_day: &NaiveDate, target: Option<String>) {
     for (cal_name, cal) in cals {
         println!("Pulling from calendar '{}' ({})...", cal_name, cal.uri);
 
-        let ical_content = match reqwest::blocking::get(&cal.uri) {
-            Ok(resp) => match resp.text() {
-                Ok(text) => text,
-                Err(e) => {
-                    eprintln!("Error: Could not read response from '{}': {}", cal.uri, e);
-                    continue;
-                }
-            },
+        let documents = match documents_of(cal, for_day, offline) {
+            Ok(documents) => documents,
             Err(e) => {
                 eprintln!("Error: Could not fetch calendar '{}': {}", cal.uri, e);
                 continue;
             }
         };
 
-        let cal_day_plan = day_plan_from_ical(&ical_content, *for_day, cal_name);
-
-        if cal_day_plan.blocks.is_empty() {
-            println!("  No events found for {} in calendar '{}'.", date_str, cal_name);
-            continue;
+        let (blocks, remove_missing) = blocks_of_documents(&documents, for_day, cal_name, cal);
+        if blocks.is_empty() {
+            println!(
+                "  No events found for {} in calendar '{}'.",
+                date_str, cal_name
+            );
         }
-
-        let mut new_blocks_added = 0;
-        for cal_block in &cal_day_plan.blocks {
-            let conflict = existing_plan
-                .blocks
-                .iter()
-                .any(|b| b.period_str == cal_block.period_str);
-
-            if conflict {
-                println!(
-                    "  Warning: Skipping '{}' ({}) — a block at {} already exists.",
-                    cal_block.desc, cal_name, cal_block.period_str
-                );
-            } else {
-                existing_plan.blocks.push(cal_block.clone());
-                new_blocks_added += 1;
-            }
+        if !remove_missing {
+            println!("  Keeping the blocks pulled from '{}' before.", cal_name);
         }
 
-        if new_blocks_added > 0 {
-            println!("  Added {} block(s) from '{}'.", new_blocks_added, cal_name);
+        let changes = update_pulled_blocks(&mut existing_plan, &blocks, cal_name, remove_missing);
+        if changes.added > 0 {
+            println!("  Added {} block(s) from '{}'.", changes.added, cal_name);
+        }
+        if changes.updated > 0 {
+            println!(
+                "  Updated {} block(s) from '{}'.",
+                changes.updated, cal_name
+            );
+        }
+        if changes.removed > 0 {
+            println!(
+                "  Removed {} block(s) of events no longer in '{}'.",
+                changes.removed, cal_name
+            );
         }
     }
 
@@ -96,7 +92,195 @@ pub fn command(config: Config, for_day: &NaiveDate, target: Option<String>) {
     println!("Written to '{}'.", target_dir.name);
 }
 
-fn resolve_target_dir<'a>(config: &'a Config, target: Option<&str>) -> Result<&'a Dir, String> {
+/// The iCalendar documents of a calendar. The cached copy is used when
+/// offline, when it is younger than the `max_age` of the calendar, when
+/// the server reports no changes or when the server can't be reached.
+/// The blocks of the events in `documents`, and whether the blocks of
+/// events missing from them are removed. A fetched calendar is complete
+/// even without any events, e.g. once the last event of the day was
+/// cancelled. A local directory without a readable calendar, e.g. one
+/// that is still being synced, and documents that all fail to parse
+/// are not.
+fn blocks_of_documents(
+    documents: &[String],
+    for_day: &NaiveDate,
+    cal_name: &str,
+    cal: &Cal,
+) -> (Vec<Block>, bool) {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut parsed_documents = 0;
+    for (i, ical_content) in documents.iter().enumerate() {
+        match day_plan_from_ical(ical_content, *for_day, cal_name, &cal.format, &cal.filter) {
+            Ok(day_plan) => {
+                blocks.extend(day_plan.blocks);
+                parsed_documents += 1;
+            }
+            Err(e) => println!(
+                "  Warning: Skipping document {} of '{}'. {}",
+                i + 1,
+                cal_name,
+                e
+            ),
+        }
+    }
+    let remove_missing = if documents.is_empty() {
+        !is_local(cal)
+    } else {
+        parsed_documents > 0
+    };
+    (blocks, remove_missing)
+}
+
+fn documents_of(cal: &Cal, for_day: &NaiveDate, offline: bool) -> Result<Vec<String>, String> {
+    if is_local(cal) {
+        return fetch(cal, for_day, for_day, None).map(|f| f.unwrap().documents);
+    }
+
+    let key = cal_cache::cache_key(cal, for_day, for_day);
+    let cached = cal_cache::load(&key);
+    let now = Utc::now().timestamp();
+    if let Some(cached) = &cached
+        && (offline || cached.is_fresh(cal.max_age, now))
+    {
+        println!("  Using the cached copy from {}.", cached.fetched_at_str());
+        return Ok(cached.documents.clone());
+    }
+    if offline {
+        return Err("No cached copy, pull once without --offline".to_string());
+    }
+
+    let feed = match (fetch(cal, for_day, for_day, cached.as_ref()), cached) {
+        (Ok(Some(fetched)), _) => CachedFeed {
+            uri: cal.uri.clone(),
+            fetched_at: now,
+            etag: fetched.etag,
+            last_modified: fetched.last_modified,
+            documents: fetched.documents,
+        },
+        (Ok(None), Some(cached)) => {
+            println!("  Not modified since {}.", cached.fetched_at_str());
+            CachedFeed {
+                fetched_at: now,
+                ..cached
+            }
+        }
+        (Ok(None), None) => return Err("server responded 304 Not Modified".to_string()),
+        (Err(e), Some(cached)) => {
+            println!(
+                "  Warning: {}. Using the cached copy from {}.",
+                e,
+                cached.fetched_at_str()
+            );
+            return Ok(cached.documents);
+        }
+        (Err(e), None) => return Err(e),
+    };
+    if let Err(e) = cal_cache::save(&key, &feed) {
+        println!("  Warning: Could not cache calendar '{}': {}", cal.uri, e);
+    }
+    Ok(feed.documents)
+}
+
+/// What a pull changed in a day plan
+#[derive(Debug, Default, PartialEq)]
+struct PullChanges {
+    added: usize,
+    updated: usize,
+    removed: usize,
+}
+
+/// Brings the blocks pulled from a calendar up to date with `blocks`.
+/// Blocks pulled before are found by the UID of their event. Their
+/// period and description are only replaced when they changed in the
+/// event since the last pull, so edits in the day plan, like added tags,
+/// are kept otherwise. With `remove_missing`, blocks whose event is gone
+/// or cancelled are removed. Other blocks are added like in
+/// `merge_blocks`.
+fn update_pulled_blocks(
+    plan: &mut DayPlan,
+    blocks: &[Block],
+    cal_name: &str,
+    remove_missing: bool,
+) -> PullChanges {
+    let mut changes = PullChanges::default();
+    let pulled_before = |b: &Block| b.origin == cal_name && b.uid.is_some();
+
+    if remove_missing {
+        let count_before = plan.blocks.len();
+        plan.blocks
+            .retain(|b| !pulled_before(b) || blocks.iter().any(|block| block.uid == b.uid));
+        changes.removed = count_before - plan.blocks.len();
+    }
+
+    let mut new_blocks = Vec::new();
+    for block in blocks {
+        let pulled_as = PulledAs::of(block);
+        let same_event = plan.blocks.iter().position(|b| {
+            if pulled_before(b) {
+                b.uid == block.uid
+            } else {
+                // Blocks pulled before UIDs were tracked
+                b.origin == cal_name && b.period_str == block.period_str && b.desc == block.desc
+            }
+        });
+        let Some(i) = same_event else {
+            new_blocks.push(Block {
+                pulled_as: Some(pulled_as),
+                ..block.clone()
+            });
+            continue;
+        };
+
+        let existing = &plan.blocks[i];
+        // Blocks of older versions look like they were pulled
+        let last_pulled_as = existing.pulled_as.unwrap_or(PulledAs::of(existing));
+        let period = if pulled_as.period != last_pulled_as.period {
+            &block.period_str
+        } else {
+            &existing.period_str
+        };
+        let desc = if pulled_as.desc != last_pulled_as.desc {
+            &block.desc
+        } else {
+            &existing.desc
+        };
+        if *period != existing.period_str || *desc != existing.desc {
+            changes.updated += 1;
+        }
+        plan.blocks[i] = Block {
+            uid: block.uid.clone(),
+            pulled_as: Some(pulled_as),
+            ..Block::new(period, cal_name, desc)
+        };
+    }
+    changes.added = merge_blocks(plan, &new_blocks, cal_name);
+    changes
+}
+
+/// Adds `blocks` to the plan, skipping those whose period is already
+/// taken by an existing block. All-day blocks only conflict with one of
+/// the same description. Returns the number of added blocks.
+pub fn merge_blocks(plan: &mut DayPlan, blocks: &[Block], source_name: &str) -> usize {
+    let mut new_blocks_added = 0;
+    for block in blocks {
+        let conflict = plan.blocks.iter().any(|b| {
+            b.period_str == block.period_str && (!block.is_all_day() || b.desc == block.desc)
+        });
+
+        if conflict {
+            println!(
+                "  Warning: Skipping '{}' ({}) — a block at {} already exists.",
+                block.desc, source_name, block.period_str
+            );
+        } else {
+            plan.blocks.push(block.clone());
+            new_blocks_added += 1;
+        }
+    }
+    new_blocks_added
+}
+
+pub fn resolve_target_dir<'a>(config: &'a Config, target: Option<&str>) -> Result<&'a Dir, String> {
     match target {
         Some(key) => config.dirs.get(key).ok_or_else(|| {
             let available: Vec<&str> = config.dirs.keys().map(|k| k.as_str()).collect();
@@ -119,3 +303,229 @@ fn resolve_target_dir<'a>(config: &'a Config, target: Option<&str>) -> Result<&'
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day_plan::Source;
+
+    fn pulled(period_str: &str, desc: &str, uid: &str) -> Block {
+        Block {
+            uid: Some(uid.to_string()),
+            ..Block::new(period_str, "team", desc)
+        }
+    }
+
+    fn plan(blocks: Vec<Block>) -> DayPlan {
+        DayPlan {
+            origin: "Work".to_string(),
+            day: NaiveDate::from_ymd_opt(2025, 3, 10),
+            source: Source::ICalendar,
+            blocks,
+        }
+    }
+
+    /// The block strings without the hashes of the pulled state
+    fn block_strings(plan: &DayPlan) -> Vec<String> {
+        plan.blocks
+            .iter()
+            .map(|b| {
+                Block {
+                    pulled_as: None,
+                    ..b.clone()
+                }
+                .to_block_string(true)
+            })
+            .collect()
+    }
+
+    /// Pulls `blocks` into `plan` and writes and reads the day plan again
+    fn pull(plan: &mut DayPlan, blocks: &[Block], remove_missing: bool) -> PullChanges {
+        let changes = update_pulled_blocks(plan, blocks, "team", remove_missing);
+        plan.blocks = plan
+            .blocks
+            .iter()
+            .map(|b| Block::parse_block_string("Work", &b.to_block_string(true)).unwrap())
+            .collect();
+        changes
+    }
+
+    #[test]
+    fn test_update_pulled_blocks() {
+        let mut plan = plan(vec![
+            Block::new("09:00 - 10:00", "Work", "Deep work"),
+            pulled("10:00 - 10:30", "Standup", "standup"),
+            pulled("11:00 - 12:00", "Planning", "planning"),
+            pulled("14:00 - 15:00", "Retro", "retro"),
+            Block::new("16:00 - 17:00", "team", "Review"),
+            pulled("16:00 - 17:00", "Sales sync", "sales"),
+        ]);
+        plan.blocks[5].origin = "crm".to_string();
+
+        let changes = pull(
+            &mut plan,
+            &[
+                pulled("10:00 - 10:30", "Standup", "standup"),
+                pulled("13:00 - 14:00", "Planning", "planning"),
+                pulled("16:00 - 17:00", "Review", "review"),
+                pulled("09:00 - 10:00", "Interview", "interview"),
+                pulled("17:00 - 18:00", "1:1", "one-on-one"),
+            ],
+            true,
+        );
+
+        assert_eq!(
+            changes,
+            PullChanges {
+                added: 1,
+                updated: 1,
+                removed: 1,
+            }
+        );
+        assert_eq!(
+            block_strings(&plan),
+            vec![
+                "09:00 - 10:00 (Work) Deep work",
+                "10:00 - 10:30 (team) Standup %%uid:standup%%",
+                "13:00 - 14:00 (team) Planning %%uid:planning%%",
+                "16:00 - 17:00 (team) Review %%uid:review%%",
+                "16:00 - 17:00 (crm) Sales sync %%uid:sales%%",
+                "17:00 - 18:00 (team) 1:1 %%uid:one-on-one%%",
+            ]
+        );
+        assert!(
+            plan.blocks
+                .iter()
+                .filter(|b| b.origin == "team")
+                .all(|b| b.pulled_as.is_some())
+        );
+    }
+
+    #[test]
+    fn test_edits_are_kept_unless_the_event_changed() {
+        let mut plan = plan(vec![]);
+        pull(
+            &mut plan,
+            &[
+                pulled("10:00 - 11:00", "Sync", "sync"),
+                pulled("13:00 - 14:00", "Review", "review"),
+            ],
+            true,
+        );
+        plan.blocks[0].desc = "Sync @client/acme".to_string();
+        plan.blocks[1].period_str = "13:00 - 14:30".to_string();
+
+        let unchanged = pull(
+            &mut plan,
+            &[
+                pulled("10:00 - 11:00", "Sync", "sync"),
+                pulled("13:00 - 14:00", "Review", "review"),
+            ],
+            true,
+        );
+        assert_eq!(unchanged, PullChanges::default());
+        assert_eq!(
+            block_strings(&plan),
+            vec![
+                "10:00 - 11:00 (team) Sync @client/acme %%uid:sync%%",
+                "13:00 - 14:30 (team) Review %%uid:review%%",
+            ]
+        );
+
+        // The sync moved, the review was renamed
+        let changed = pull(
+            &mut plan,
+            &[
+                pulled("11:00 - 12:00", "Sync", "sync"),
+                pulled("13:00 - 14:00", "Design review", "review"),
+            ],
+            true,
+        );
+        assert_eq!(changed.updated, 2);
+        assert_eq!(
+            block_strings(&plan),
+            vec![
+                "11:00 - 12:00 (team) Sync @client/acme %%uid:sync%%",
+                "13:00 - 14:30 (team) Design review %%uid:review%%",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks_of_older_versions_are_updated_once() {
+        let mut plan = plan(vec![pulled("10:00 - 11:00", "Sync", "sync")]);
+
+        let unchanged = pull(&mut plan, &[pulled("10:00 - 11:00", "Sync", "sync")], true);
+        assert_eq!(unchanged, PullChanges::default());
+        assert!(plan.blocks[0].pulled_as.is_some());
+
+        let mut plan = super::tests::plan(vec![pulled("10:00 - 11:00", "Sync", "sync")]);
+        let moved = pull(&mut plan, &[pulled("10:30 - 11:30", "Sync", "sync")], true);
+        assert_eq!(moved.updated, 1);
+        assert_eq!(plan.blocks[0].period_str, "10:30 - 11:30");
+    }
+
+    #[test]
+    fn test_nothing_is_removed_without_a_calendar() {
+        let mut plan = plan(vec![pulled("10:00 - 11:00", "Sync", "sync")]);
+
+        let changes = pull(&mut plan, &[], false);
+
+        assert_eq!(changes, PullChanges::default());
+        assert_eq!(plan.blocks.len(), 1);
+    }
+
+    fn cal(uri: &str) -> Cal {
+        toml::from_str(&format!("uri = '{}'", uri)).unwrap()
+    }
+
+    const CANCELLED_SYNC: &str = "BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//test//EN
+BEGIN:VEVENT
+UID:sync
+DTSTAMP:20250301T120000Z
+DTSTART:20250310T130000Z
+DTEND:20250310T140000Z
+SUMMARY:Sync
+STATUS:CANCELLED
+END:VEVENT
+END:VCALENDAR
+";
+
+    #[test]
+    fn test_last_event_of_the_day_cancelled_removes_its_block() {
+        let day = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        let caldav = cal("https://cal.example.com/work/");
+
+        // A CalDAV query for the day returns no documents at all, a feed
+        // still has the cancelled event
+        for documents in [vec![], vec![CANCELLED_SYNC.to_string()]] {
+            let mut plan = plan(vec![pulled("10:00 - 11:00", "Sync", "sync")]);
+
+            let (blocks, remove_missing) = blocks_of_documents(&documents, &day, "team", &caldav);
+            let changes = pull(&mut plan, &blocks, remove_missing);
+
+            assert!(blocks.is_empty());
+            assert_eq!(changes.removed, 1);
+            assert!(plan.blocks.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_blocks_are_kept_without_a_readable_calendar() {
+        let day = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+
+        let (_, remove_missing) = blocks_of_documents(&[], &day, "team", &cal("file:///vdir"));
+        assert!(!remove_missing);
+
+        let garbage = ["garbage".to_string()];
+        let (_, remove_missing) = blocks_of_documents(
+            &garbage,
+            &day,
+            "team",
+            &cal("https://cal.example.com/work/"),
+        );
+        assert!(!remove_missing);
+    }
+}
@@ -0,0 +1,447 @@
+use chrono::NaiveDate;
+use icalendar::Calendar;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::block::Block;
+use crate::blockary_cfg::{Config, Push};
+use crate::cal_day_plan::{Privacy, block_event, block_uid};
+use crate::cal_fetch::Credentials;
+use crate::cmd_pull::resolve_target_dir;
+use crate::day_plan::{DayPlanRepo, DayPlanRepoType};
+
+/// A block as it was last pushed, to find its event again after the
+/// block was edited or moved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PushedBlock {
+    uid: String,
+    period: String,
+    desc: String,
+    /// Missing in the state of older versions, which then updates the
+    /// event once
+    privacy: Option<Privacy>,
+}
+
+/// The pushed blocks per collection URL and day (YYYY-MM-DD)
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PushState {
+    collections: BTreeMap<String, BTreeMap<String, Vec<PushedBlock>>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum PushAction<'a> {
+    Create { uid: String, block: &'a Block },
+    Update { uid: String, block: &'a Block },
+    Delete { uid: String },
+}
+
+pub fn command(config: &Config, for_day: &NaiveDate, dry_run: bool) {
+    let Some(push) = &config.push else {
+        eprintln!("Error: Nothing to push to. Add a [push] section to your blockary.toml.");
+        return;
+    };
+    let dir = match resolve_target_dir(config, push.origin.as_deref()) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let repo = DayPlanRepo {
+        name: dir.name.clone(),
+        repo_type: DayPlanRepoType::MarkdownDirectory {
+            dir: dir.path.clone(),
+        },
+    };
+    let Some(plan) = repo.all_of_day(for_day).into_iter().next() else {
+        println!(
+            "Warning: No file found for {} in '{}' ({}). Skipping.",
+            for_day, dir.name, dir.path
+        );
+        return;
+    };
+
+    let mut state = load_state();
+    let previous = state
+        .collections
+        .get(&push.url)
+        .and_then(|days| days.get(&for_day.to_string()))
+        .cloned()
+        .unwrap_or_default();
+    let blocks: Vec<&Block> = plan
+        .only_original_blocks_slice()
+        .into_iter()
+        .filter(|b| b.times().is_some())
+        .collect();
+    let (actions, mut pushed) = plan_push(&previous, &blocks, &plan.origin, for_day, push.privacy);
+    if actions.is_empty() {
+        println!("Nothing changed since the last push.");
+        return;
+    }
+
+    let credentials = if dry_run {
+        Credentials::default()
+    } else {
+        match Credentials::resolve(&push.auth) {
+            Ok(credentials) => credentials,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return;
+            }
+        }
+    };
+    let client = Client::new();
+    for action in &actions {
+        let (verb, uid) = match action {
+            PushAction::Create { uid, .. } => ("Create", uid),
+            PushAction::Update { uid, .. } => ("Update", uid),
+            PushAction::Delete { uid } => ("Delete", uid),
+        };
+        if dry_run {
+            println!("{} {}", verb, describe(action, &previous));
+            continue;
+        }
+        match send(&client, push, &credentials, action, for_day) {
+            Ok(_) => println!("{} {}", verb, describe(action, &previous)),
+            Err(e) => {
+                eprintln!("Error: Could not {} {}: {}", verb.to_lowercase(), uid, e);
+                // Keep what the server has, so the next push tries again
+                pushed.retain(|p| &p.uid != uid);
+                if let Some(old) = previous.iter().find(|p| &p.uid == uid) {
+                    pushed.push(old.clone());
+                }
+            }
+        }
+    }
+
+    if !dry_run {
+        state
+            .collections
+            .entry(push.url.clone())
+            .or_default()
+            .insert(for_day.to_string(), pushed);
+        if let Err(e) = save_state(&state) {
+            eprintln!("Error: Could not save the push state: {}", e);
+        }
+    }
+}
+
+fn describe(action: &PushAction, previous: &[PushedBlock]) -> String {
+    match action {
+        PushAction::Create { block, .. } | PushAction::Update { block, .. } => {
+            block.to_block_string(false)
+        }
+        PushAction::Delete { uid } => previous
+            .iter()
+            .find(|p| &p.uid == uid)
+            .map(|p| format!("{} {}", p.period, p.desc))
+            .unwrap_or_else(|| uid.clone()),
+    }
+}
+
+/// Decides which events to create, update or delete so the collection
+/// matches `blocks`. Pushed blocks are matched to the current blocks
+/// first by period and description, then by period alone (the block was
+/// edited) and then by description alone (the block was moved). Blocks
+/// pushed with another privacy are updated as well. Returns the actions
+/// and the new state of the day.
+fn plan_push<'a>(
+    previous: &[PushedBlock],
+    blocks: &[&'a Block],
+    origin: &str,
+    day: &NaiveDate,
+    privacy: Privacy,
+) -> (Vec<PushAction<'a>>, Vec<PushedBlock>) {
+    let mut unmatched_previous: Vec<&PushedBlock> = previous.iter().collect();
+    let mut uids: Vec<Option<String>> = vec![None; blocks.len()];
+    let mut actions = Vec::new();
+
+    let matchers: [fn(&PushedBlock, &Block) -> bool; 3] = [
+        |p, b| p.period == b.period_str && p.desc == b.desc,
+        |p, b| p.period == b.period_str,
+        |p, b| p.desc == b.desc,
+    ];
+    for (round, matches) in matchers.iter().enumerate() {
+        for (i, block) in blocks.iter().enumerate() {
+            if uids[i].is_some() {
+                continue;
+            }
+            if let Some(pos) = unmatched_previous.iter().position(|p| matches(p, block)) {
+                let p = unmatched_previous.remove(pos);
+                uids[i] = Some(p.uid.clone());
+                if round > 0 || p.privacy != Some(privacy) {
+                    actions.push(PushAction::Update {
+                        uid: p.uid.clone(),
+                        block,
+                    });
+                }
+            }
+        }
+    }
+
+    for p in &unmatched_previous {
+        actions.push(PushAction::Delete { uid: p.uid.clone() });
+    }
+
+    for (i, block) in blocks.iter().enumerate() {
+        if uids[i].is_some() {
+            continue;
+        }
+        let taken = |uid: &String| {
+            previous.iter().any(|p| &p.uid == uid) || uids.iter().flatten().any(|u| u == uid)
+        };
+        let mut uid = block_uid(origin, day, &block.period_str);
+        let mut n = 1;
+        while taken(&uid) {
+            uid = block_uid(origin, day, &format!("{}#{}", block.period_str, n));
+            n += 1;
+        }
+        actions.push(PushAction::Create {
+            uid: uid.clone(),
+            block,
+        });
+        uids[i] = Some(uid);
+    }
+
+    let pushed = blocks
+        .iter()
+        .zip(uids)
+        .map(|(block, uid)| PushedBlock {
+            uid: uid.unwrap(),
+            period: block.period_str.clone(),
+            desc: block.desc.clone(),
+            privacy: Some(privacy),
+        })
+        .collect();
+    (actions, pushed)
+}
+
+fn event_url(push: &Push, uid: &str) -> String {
+    let file_name: String = uid
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}/{}.ics", push.url.trim_end_matches('/'), file_name)
+}
+
+fn send(
+    client: &Client,
+    push: &Push,
+    credentials: &Credentials,
+    action: &PushAction,
+    day: &NaiveDate,
+) -> Result<(), String> {
+    let request = match action {
+        PushAction::Create { uid, block } | PushAction::Update { uid, block } => {
+            let event = block_event(block, day, uid, push.privacy).unwrap();
+            let ical = Calendar::new().push(event).done().to_string();
+            client
+                .put(event_url(push, uid))
+                .header("Content-Type", "text/calendar; charset=utf-8")
+                .body(ical)
+        }
+        PushAction::Delete { uid } => client.delete(event_url(push, uid)),
+    };
+    let response = credentials
+        .authorize(request)
+        .send()
+        .map_err(|e| e.to_string())?;
+    let deleted_already = matches!(action, PushAction::Delete { .. })
+        && response.status() == reqwest::StatusCode::NOT_FOUND;
+    if response.status().is_success() || deleted_already {
+        Ok(())
+    } else {
+        Err(format!("server responded {}", response.status()))
+    }
+}
+
+fn state_file_path() -> PathBuf {
+    let state_home = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::home_dir()
+            .expect("$HOME is not set")
+            .join(".local")
+            .join("state"),
+    };
+    state_home.join("blockary").join("push.json")
+}
+
+fn load_state() -> PushState {
+    fs::read_to_string(state_file_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_state(state: &PushState) -> std::io::Result<()> {
+    let path = state_file_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(state).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, 10).unwrap()
+    }
+
+    fn pushed(uid: &str, period: &str, desc: &str) -> PushedBlock {
+        PushedBlock {
+            uid: uid.to_string(),
+            period: period.to_string(),
+            desc: desc.to_string(),
+            privacy: Some(Privacy::Full),
+        }
+    }
+
+    #[test]
+    fn test_first_push_creates_all_blocks() {
+        let blocks = [
+            Block::new("09:00 - 10:00", "Work", "Standup"),
+            Block::new("10:00 - 11:00", "Work", "Review"),
+        ];
+        let blocks: Vec<&Block> = blocks.iter().collect();
+
+        let (actions, state) = plan_push(&[], &blocks, "Work", &day(), Privacy::Full);
+
+        assert_eq!(actions.len(), 2);
+        assert!(matches!(actions[0], PushAction::Create { .. }));
+        assert_eq!(state[0].uid, block_uid("Work", &day(), "09:00 - 10:00"));
+        assert_eq!(state[1].desc, "Review");
+    }
+
+    #[test]
+    fn test_edits_update_and_removals_delete() {
+        let previous = vec![
+            pushed("a", "09:00 - 10:00", "Standup"),
+            pushed("b", "10:00 - 11:00", "Review"),
+            pushed("c", "11:00 - 12:00", "Planning"),
+            pushed("d", "13:00 - 14:00", "Cancelled"),
+        ];
+        let blocks = [
+            Block::new("09:00 - 10:00", "Work", "Standup"),
+            Block::new("10:00 - 11:00", "Work", "Review @p/alpha"),
+            Block::new("15:00 - 16:00", "Work", "Planning"),
+            Block::new("16:00 - 17:00", "Work", "New"),
+        ];
+        let block_refs: Vec<&Block> = blocks.iter().collect();
+
+        let (actions, state) = plan_push(&previous, &block_refs, "Work", &day(), Privacy::Full);
+
+        let new_uid = block_uid("Work", &day(), "16:00 - 17:00");
+        assert_eq!(
+            actions,
+            vec![
+                PushAction::Update {
+                    uid: "b".to_string(),
+                    block: &blocks[1]
+                },
+                PushAction::Update {
+                    uid: "c".to_string(),
+                    block: &blocks[2]
+                },
+                PushAction::Delete {
+                    uid: "d".to_string()
+                },
+                PushAction::Create {
+                    uid: new_uid.clone(),
+                    block: &blocks[3]
+                },
+            ]
+        );
+        let uids: Vec<&str> = state.iter().map(|p| p.uid.as_str()).collect();
+        assert_eq!(uids, vec!["a", "b", "c", new_uid.as_str()]);
+    }
+
+    #[test]
+    fn test_unchanged_blocks_need_no_push() {
+        let previous = vec![pushed("a", "09:00 - 10:00", "Standup")];
+        let blocks = [Block::new("09:00 - 10:00", "Work", "Standup")];
+        let blocks: Vec<&Block> = blocks.iter().collect();
+
+        let (actions, state) = plan_push(&previous, &blocks, "Work", &day(), Privacy::Full);
+
+        assert!(actions.is_empty());
+        assert_eq!(state, previous);
+    }
+
+    #[test]
+    fn test_changed_privacy_updates_events() {
+        let previous = vec![
+            pushed("a", "09:00 - 10:00", "Standup"),
+            PushedBlock {
+                privacy: None,
+                ..pushed("b", "10:00 - 11:00", "Review")
+            },
+        ];
+        let blocks = [
+            Block::new("09:00 - 10:00", "Work", "Standup"),
+            Block::new("10:00 - 11:00", "Work", "Review"),
+        ];
+        let blocks: Vec<&Block> = blocks.iter().collect();
+
+        let (actions, state) = plan_push(&previous, &blocks, "Work", &day(), Privacy::Full);
+        assert_eq!(
+            actions,
+            vec![PushAction::Update {
+                uid: "b".to_string(),
+                block: blocks[1]
+            }]
+        );
+        assert_eq!(state[1].privacy, Some(Privacy::Full));
+
+        let (actions, state) = plan_push(&state, &blocks, "Work", &day(), Privacy::Busy);
+        assert_eq!(actions.len(), 2);
+        assert!(state.iter().all(|p| p.privacy == Some(Privacy::Busy)));
+    }
+
+    #[test]
+    fn test_state_of_older_versions_loads() {
+        let state: PushState = serde_json::from_str(
+            r#"{"collections":{"u":{"2025-03-10":[{"uid":"a","period":"09:00 - 10:00","desc":"Standup"}]}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(state.collections["u"]["2025-03-10"][0].privacy, None);
+    }
+
+    #[test]
+    fn test_new_uids_do_not_clash_with_moved_blocks() {
+        let moved_uid = block_uid("Work", &day(), "09:00 - 10:00");
+        let previous = vec![pushed(&moved_uid, "09:00 - 10:00", "Standup")];
+        let blocks = [
+            Block::new("09:30 - 10:30", "Work", "Standup"),
+            Block::new("09:00 - 09:30", "Work", "Coffee"),
+        ];
+        let blocks: Vec<&Block> = blocks.iter().collect();
+
+        let (_, state) = plan_push(&previous, &blocks, "Work", &day(), Privacy::Full);
+
+        assert_eq!(state[0].uid, moved_uid);
+        assert_ne!(state[1].uid, moved_uid);
+    }
+
+    #[test]
+    fn test_event_url() {
+        let push: Push = toml::from_str(r#"url = "http://localhost:5232/me/blocks/""#).unwrap();
+
+        assert_eq!(
+            event_url(&push, "0123abcd@blockary"),
+            "http://localhost:5232/me/blocks/0123abcd-blockary.ics"
+        );
+    }
+}
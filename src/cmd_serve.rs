@@ -0,0 +1,373 @@
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration as StdDuration;
+
+use crate::block::Block;
+use crate::blockary_cfg::Config;
+use crate::cal_day_plan::{Privacy, block_summary, ical_from_day_plans};
+use crate::day_plan::{DayPlan, DayPlanRepo, DayPlanRepoType};
+
+/// How many days before and after today `/calendar.ics` covers by default
+const DEFAULT_CALENDAR_DAYS: i64 = 30;
+/// Connections are handled one at a time, so a client that stops
+/// sending must not keep the others waiting for long
+const READ_TIMEOUT: StdDuration = StdDuration::from_secs(5);
+/// The most bytes read of a request line and its headers
+const MAX_REQUEST_BYTES: u64 = 16 * 1024;
+
+pub struct ServeOptions {
+    pub bind: String,
+    pub port: u16,
+    pub privacy: Privacy,
+}
+
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn error(status: &'static str, message: &str) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", message),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DayJson {
+    day: String,
+    plans: Vec<DayPlanJson>,
+}
+
+#[derive(Serialize)]
+struct DayPlanJson {
+    origin: String,
+    blocks: Vec<BlockJson>,
+}
+
+#[derive(Serialize)]
+struct BlockJson {
+    period: String,
+    start: Option<String>,
+    end: Option<String>,
+    origin: String,
+    desc: String,
+    duration: u32,
+    tags: Vec<String>,
+}
+
+impl BlockJson {
+    /// The block with as much of its description and tags as `privacy`
+    /// allows, like the events of `/calendar.ics`
+    fn new(block: &Block, privacy: Privacy) -> Self {
+        let times = block.times();
+        let tags = match privacy {
+            Privacy::Full | Privacy::Tags => block.tags.iter().map(|t| t.tagls.join("/")).collect(),
+            Privacy::Busy => vec![],
+        };
+        BlockJson {
+            period: block.period_str.clone(),
+            start: times.map(|(start, _)| start.format("%H:%M").to_string()),
+            end: times.map(|(_, end)| end.format("%H:%M").to_string()),
+            origin: block.origin.clone(),
+            desc: block_summary(block, privacy),
+            duration: block.duration,
+            tags,
+        }
+    }
+}
+
+pub fn command(config: &Config, options: &ServeOptions) {
+    let address = format!("{}:{}", options.bind, options.port);
+    let listener = match TcpListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Error: Could not listen on {}: {}", address, e);
+            return;
+        }
+    };
+    println!(
+        "Serving on http://{}/calendar.ics and http://{}/api/days/YYYY-MM-DD",
+        address, address
+    );
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(stream, config, options.privacy) {
+                    eprintln!("Error: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Error: Connection failed: {}", e),
+        }
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    config: &Config,
+    privacy: Privacy,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, none of them are needed
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim() != "" {
+        header.clear();
+    }
+
+    let today = chrono::Local::now().date_naive();
+    let response = respond(
+        &request_line,
+        |from, to| load_day_plans(config, from, to),
+        privacy,
+        &today,
+    );
+    println!("{} -> {}", request_line.trim(), response.status);
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn load_day_plans(
+    config: &Config,
+    from_inclusive: &NaiveDate,
+    to_inclusive: &NaiveDate,
+) -> Vec<DayPlan> {
+    config
+        .dirs
+        .values()
+        .flat_map(|dir| {
+            DayPlanRepo {
+                name: dir.name.clone(),
+                repo_type: DayPlanRepoType::MarkdownDirectory {
+                    dir: dir.path.clone(),
+                },
+            }
+            .all_between(from_inclusive, to_inclusive)
+        })
+        .collect()
+}
+
+/// Answers a request like `GET /api/days/2025-03-10 HTTP/1.1`, loading
+/// the day plans between two days with `load`.
+fn respond(
+    request_line: &str,
+    load: impl Fn(&NaiveDate, &NaiveDate) -> Vec<DayPlan>,
+    privacy: Privacy,
+    today: &NaiveDate,
+) -> Response {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Response::error("400 Bad Request", "Malformed request");
+    };
+    if method != "GET" {
+        return Response::error("405 Method Not Allowed", "Only GET is supported");
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    if path == "/calendar.ics" {
+        let param = |name: &str| {
+            query
+                .split('&')
+                .filter_map(|p| p.split_once('='))
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        };
+        let from = match param("from") {
+            Some(Ok(from)) => from,
+            Some(Err(_)) => return Response::error("400 Bad Request", "from must be YYYY-MM-DD"),
+            None => *today - Duration::days(DEFAULT_CALENDAR_DAYS),
+        };
+        let to = match param("to") {
+            Some(Ok(to)) => to,
+            Some(Err(_)) => return Response::error("400 Bad Request", "to must be YYYY-MM-DD"),
+            None => *today + Duration::days(DEFAULT_CALENDAR_DAYS),
+        };
+        return Response {
+            status: "200 OK",
+            content_type: "text/calendar; charset=utf-8",
+            body: ical_from_day_plans(&load(&from, &to), privacy),
+        };
+    }
+
+    if let Some(date) = path.strip_prefix("/api/days/") {
+        let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
+            return Response::error("400 Bad Request", "The day must be YYYY-MM-DD");
+        };
+        let mut plans: Vec<DayPlanJson> = load(&day, &day)
+            .iter()
+            .map(|dp| DayPlanJson {
+                origin: dp.origin.clone(),
+                blocks: dp
+                    .only_original_blocks_slice()
+                    .into_iter()
+                    .map(|block| BlockJson::new(block, privacy))
+                    .collect(),
+            })
+            .collect();
+        plans.sort_by(|a, b| a.origin.cmp(&b.origin));
+        let day_json = DayJson {
+            day: day.to_string(),
+            plans,
+        };
+        return Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: serde_json::to_string(&day_json).unwrap(),
+        };
+    }
+
+    Response::error("404 Not Found", "Not found")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day_plan::Source;
+    use std::io::Read;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, d).unwrap()
+    }
+
+    fn load(from: &NaiveDate, to: &NaiveDate) -> Vec<DayPlan> {
+        vec![DayPlan {
+            origin: "Work".to_string(),
+            day: Some(day(10)),
+            source: Source::ICalendar,
+            blocks: vec![
+                Block::new("09:00 - 10:00", "Work", "Standup @meetings"),
+                Block::new("12:00 - 13:00", "Home", "Doctor @private/health"),
+            ],
+        }]
+        .into_iter()
+        .filter(|dp| *from <= dp.day.unwrap() && dp.day.unwrap() <= *to)
+        .collect()
+    }
+
+    #[test]
+    fn test_day_as_json() {
+        let response = respond(
+            "GET /api/days/2025-03-10 HTTP/1.1",
+            load,
+            Privacy::Full,
+            &day(10),
+        );
+
+        assert_eq!(response.status, "200 OK");
+        // The synced block of another origin is left out
+        assert_eq!(
+            response.body,
+            r#"{"day":"2025-03-10","plans":[{"origin":"Work","blocks":[{"period":"09:00 - 10:00","start":"09:00","end":"10:00","origin":"Work","desc":"Standup @meetings","duration":60,"tags":["meetings"]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_day_as_json_respects_privacy() {
+        let body =
+            |privacy| respond("GET /api/days/2025-03-10 HTTP/1.1", load, privacy, &day(1)).body;
+
+        assert!(
+            body(Privacy::Tags).contains(r#""desc":"@meetings","duration":60,"tags":["meetings"]"#)
+        );
+        assert!(body(Privacy::Busy).contains(r#""desc":"Busy","duration":60,"tags":[]"#));
+        assert!(!body(Privacy::Busy).contains("Standup"));
+    }
+
+    #[test]
+    fn test_calendar_range() {
+        let response = respond(
+            "GET /calendar.ics?from=2025-03-10&to=2025-03-10 HTTP/1.1",
+            load,
+            Privacy::Full,
+            &day(1),
+        );
+        assert_eq!(response.content_type, "text/calendar; charset=utf-8");
+        assert!(response.body.contains("SUMMARY:Standup @meetings"));
+
+        let response = respond(
+            "GET /calendar.ics?from=2025-03-11 HTTP/1.1",
+            load,
+            Privacy::Full,
+            &day(1),
+        );
+        assert!(!response.body.contains("BEGIN:VEVENT"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let status = |request_line| respond(request_line, load, Privacy::Busy, &day(1)).status;
+
+        assert_eq!(status("GET /api/days/tomorrow HTTP/1.1"), "400 Bad Request");
+        assert_eq!(status("GET /calendar.ics?to=x HTTP/1.1"), "400 Bad Request");
+        assert_eq!(
+            status("POST /calendar.ics HTTP/1.1"),
+            "405 Method Not Allowed"
+        );
+        assert_eq!(status("GET /secrets HTTP/1.1"), "404 Not Found");
+        assert_eq!(status(""), "400 Bad Request");
+    }
+
+    #[test]
+    fn test_serves_over_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config: Config = toml::from_str("[dirs]").unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &config, Privacy::Busy).unwrap();
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        client
+            .write_all(b"GET /api/days/2025-03-10 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: application/json\r\n"));
+        assert!(response.ends_with(r#"{"day":"2025-03-10","plans":[]}"#));
+    }
+
+    #[test]
+    fn test_stops_reading_endless_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let config: Config = toml::from_str("[dirs]").unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &config, Privacy::Busy).unwrap();
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        // A header that never ends, exactly as long as what is read
+        let mut request = b"GET /secrets HTTP/1.1\r\nX-Padding: ".to_vec();
+        request.resize(MAX_REQUEST_BYTES as usize, b'x');
+        client.write_all(&request).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -1,65 +1,435 @@
-use chrono::Datelike;
-use chrono::Duration;
-use chrono::NaiveDate;
 use comfy_table::Table;
 use comfy_table::presets;
 
+use crate::billing;
+use crate::billing::TagMetas;
 use crate::block::Block;
 use crate::blockary_cfg;
 use crate::day_plan;
+use crate::day_plan::DayPlan;
 use crate::day_plan::DayPlanRepo;
+use crate::tag::TagAliases;
+use crate::tag::TagFilter;
+use crate::tag::TagPattern;
+use crate::tag::filter_tags;
+use crate::time_range::GroupBy;
 use crate::time_summary;
-use crate::time_summary::minutes_to_hours_minutes;
+use crate::time_summary::Allocation;
+use crate::time_summary::format_duration;
+
+/// Options that change how `spent` summarizes and presents the time.
+#[derive(Debug, Default)]
+pub struct SpentOptions {
+    /// Break the period down into columns of days, weeks or months
+    pub group_by: Option<GroupBy>,
+    /// Show durations in days of this many hours
+    pub hours_per_day: Option<u32>,
+    /// Count all-day blocks, e.g. `@pto`, as this many hours
+    pub all_day_hours: Option<u32>,
+    /// Report all origins together in one table
+    pub combined: bool,
+    /// Tag patterns to exclude in addition to the configured ones
+    pub exclude: Vec<TagPattern>,
+    /// Tag patterns to count even if they are excluded
+    pub include: Vec<TagPattern>,
+    /// List the blocks without any tag below the report
+    pub list_untagged: bool,
+    /// How the time of blocks with several tags is attributed to them
+    pub allocation: Allocation,
+    /// Only count blocks with tags matching these filters
+    pub tag_filters: Vec<TagFilter>,
+    /// Show billable time and amounts instead of the tag tree
+    pub billing: bool,
+}
 
 pub fn command(
     config: blockary_cfg::Config,
     from_inclusive: &chrono::NaiveDate,
     to_inclusive: &chrono::NaiveDate,
+    options: &SpentOptions,
 ) {
-    for (_, dir) in &config.dirs {
-        println!("\n> {}", dir.name);
-        time_spent_per_origin(from_inclusive, to_inclusive, dir);
+    let exclusions = time_summary::TagExclusions {
+        exclude: config
+            .tags
+            .exclude
+            .iter()
+            .chain(&options.exclude)
+            .cloned()
+            .collect(),
+        include: options.include.clone(),
+    };
+    let origins: Vec<OriginPlans> = config
+        .dirs
+        .values()
+        .map(|dir| {
+            OriginPlans::load(
+                dir,
+                from_inclusive,
+                to_inclusive,
+                &config.tags.aliases,
+                &exclusions,
+                &options.tag_filters,
+                options.all_day_hours,
+            )
+        })
+        .collect();
+
+    if options.combined {
+        let names: Vec<&str> = origins.iter().map(|o| o.name.as_str()).collect();
+        let names = names.join(", ");
+        println!("\n> {}", names);
+        report(
+            &origins,
+            &names,
+            from_inclusive,
+            to_inclusive,
+            &config.tags.meta,
+            options,
+        );
+    } else {
+        for origin in &origins {
+            println!("\n> {}", origin.name);
+            report(
+                std::slice::from_ref(origin),
+                &origin.name,
+                from_inclusive,
+                to_inclusive,
+                &config.tags.meta,
+                options,
+            );
+        }
+    }
+}
+
+/// The day plans of one configured directory, containing only the
+/// blocks that count towards the reported time
+struct OriginPlans {
+    name: String,
+    day_plans: Vec<DayPlan>,
+}
+
+impl OriginPlans {
+    fn load(
+        origin: &blockary_cfg::Dir,
+        from_inclusive: &chrono::NaiveDate,
+        to_inclusive: &chrono::NaiveDate,
+        aliases: &TagAliases,
+        exclusions: &time_summary::TagExclusions,
+        tag_filters: &[TagFilter],
+        all_day_hours: Option<u32>,
+    ) -> Self {
+        let repo = DayPlanRepo {
+            name: origin.name.clone(),
+            repo_type: day_plan::DayPlanRepoType::MarkdownDirectory {
+                dir: origin.path.clone(),
+            },
+        };
+        let mut day_plans = repo.all_between(from_inclusive, to_inclusive);
+        for dp in &mut day_plans {
+            for block in &mut dp.blocks {
+                block.tags = aliases.canonicalize_all(&block.tags);
+                if let Some(hours) = all_day_hours
+                    && block.is_all_day()
+                {
+                    block.duration = hours * 60;
+                }
+            }
+            dp.blocks.retain(|b| !exclusions.excludes(b));
+            if !tag_filters.is_empty() {
+                // The tags are only restricted when summing up, so that
+                // allocation still shares the time among all of them
+                dp.blocks
+                    .retain(|b| !filter_tags(&b.tags, tag_filters).is_empty());
+            }
+        }
+        OriginPlans {
+            name: origin.name.clone(),
+            day_plans,
+        }
+    }
+
+    fn between(
+        &self,
+        from_inclusive: &chrono::NaiveDate,
+        to_inclusive: &chrono::NaiveDate,
+    ) -> Self {
+        OriginPlans {
+            name: self.name.clone(),
+            day_plans: self
+                .day_plans
+                .iter()
+                .filter(|dp| {
+                    dp.day()
+                        .is_some_and(|d| *from_inclusive <= d && d <= *to_inclusive)
+                })
+                .cloned()
+                .collect(),
+        }
     }
 }
 
-pub fn time_spent_per_origin(
+fn report(
+    origins: &[OriginPlans],
+    label: &str,
     from_inclusive: &chrono::NaiveDate,
     to_inclusive: &chrono::NaiveDate,
-    origin: &blockary_cfg::Dir,
+    metas: &TagMetas,
+    options: &SpentOptions,
 ) {
-    let repo = DayPlanRepo {
-        name: origin.name.clone(),
-        repo_type: day_plan::DayPlanRepoType::MarkdownDirectory {
-            dir: origin.path.clone(),
-        },
-    };
-    let dps_in_range = repo.all_between(from_inclusive, to_inclusive);
+    if options.billing {
+        billable_time_spent(origins, metas, options);
+    } else {
+        match options.group_by {
+            Some(group_by) => {
+                time_spent_per_period(origins, from_inclusive, to_inclusive, group_by, options)
+            }
+            None => time_spent(origins, label, options),
+        }
+    }
+    if options.list_untagged {
+        print_untagged_blocks(origins, options);
+    }
+}
 
-    let mut all_blocks: Vec<&Block> = Vec::new();
-    for dp in &dps_in_range {
-        for block in &dp.only_original_blocks_slice() {
-            all_blocks.push(&block);
+fn print_untagged_blocks(origins: &[OriginPlans], options: &SpentOptions) {
+    println!("\nUntagged blocks:");
+    for origin in origins {
+        let mut day_plans: Vec<&DayPlan> = origin.day_plans.iter().collect();
+        day_plans.sort_by_key(|dp| dp.day());
+        for dp in day_plans {
+            let day = dp.day().map(|d| d.to_string()).unwrap_or_default();
+            for block in dp.only_original_blocks_slice() {
+                if block.tags.is_empty() {
+                    println!("{} {}", day, block.to_block_string(options.combined));
+                }
+            }
         }
     }
-    let tag_timings = time_summary::time_per_tag(&all_blocks, 0);
+}
+
+/// Sums up the original blocks of all origins per tag. In combined
+/// mode, each origin becomes a top-level entry with its tags below.
+/// Blocks synced into other origins are never counted twice, since
+/// only the blocks originating from a day plan's own origin are used.
+fn tag_timings(origins: &[OriginPlans], options: &SpentOptions) -> Vec<time_summary::TagTime> {
+    if options.combined {
+        let mut timings: Vec<time_summary::TagTime> = origins
+            .iter()
+            .map(|o| time_summary::TagTime {
+                tag: o.name.clone(),
+                minutes: total_time_spent(std::slice::from_ref(o), options),
+                sub_tags: time_summary::time_per_tag(
+                    &original_blocks(&o.day_plans),
+                    options.allocation,
+                    &options.tag_filters,
+                ),
+            })
+            .collect();
+        timings.sort_by_key(|tt| std::cmp::Reverse(tt.minutes));
+        timings
+    } else {
+        let day_plans: Vec<DayPlan> = origins
+            .iter()
+            .flat_map(|o| o.day_plans.iter().cloned())
+            .collect();
+        time_summary::time_per_tag(
+            &original_blocks(&day_plans),
+            options.allocation,
+            &options.tag_filters,
+        )
+    }
+}
+
+fn total_time_spent(origins: &[OriginPlans], options: &SpentOptions) -> u32 {
+    origins
+        .iter()
+        .map(|o| {
+            time_summary::total_time_spent(&o.day_plans, options.allocation, &options.tag_filters)
+        })
+        .sum()
+}
+
+fn time_spent(origins: &[OriginPlans], label: &str, options: &SpentOptions) {
+    let tag_timings = tag_timings(origins, options);
     let mut table = Table::new();
 
-    table.set_header(vec!["Tagl", "..", "..", "Time", "%"]);
+    let tag_columns = if options.combined {
+        table.set_header(vec!["Origin", "Tagl", "..", "..", "Time", "%"]);
+        4
+    } else {
+        table.set_header(vec!["Tagl", "..", "..", "Time", "%"]);
+        3
+    };
     table.load_preset(presets::UTF8_FULL_CONDENSED);
-    add_row_for_tagl(&tag_timings, &mut table, 0);
+    add_row_for_tagl(&tag_timings, &mut table, 0, tag_columns, options);
 
     println!("{table}");
 
-    let (hours, minutes) =
-        time_summary::minutes_to_hours_minutes(time_summary::total_time_spent(&dps_in_range));
     println!("--:--");
     println!(
-        "{:02}:{:02} on {} in this period",
-        hours, minutes, origin.name
+        "{} on {} in this period",
+        format_duration(total_time_spent(origins, options), options.hours_per_day),
+        label
+    );
+}
+
+/// Prints the billable time and amount for each billable tag in
+/// `[tags.meta]`, with one total per currency.
+fn billable_time_spent(origins: &[OriginPlans], metas: &TagMetas, options: &SpentOptions) {
+    let day_plans: Vec<DayPlan> = origins
+        .iter()
+        .flat_map(|o| o.day_plans.iter().cloned())
+        .collect();
+    let tag_timings = time_summary::time_per_tag(
+        &original_blocks(&day_plans),
+        options.allocation,
+        &options.tag_filters,
+    );
+    let lines = billing::billing_lines(&tag_timings, metas);
+    if lines.is_empty() {
+        println!("No billable time in this period");
+        return;
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["Tag", "Time", "Billed", "Rate", "Amount"]);
+    table.load_preset(presets::UTF8_FULL_CONDENSED);
+    let mut totals: Vec<(String, u32, f64)> = Vec::new();
+    for line in &lines {
+        let currency = line.meta.currency.clone().unwrap_or_default();
+        table.add_row(vec![
+            format!("@{}", line.account.join("/")),
+            format_duration(line.minutes, options.hours_per_day),
+            format_duration(line.billed_minutes, options.hours_per_day),
+            line.meta
+                .rate
+                .map(|r| format!("{:.2} {}", r, currency))
+                .unwrap_or_default(),
+            line.amount
+                .map(|a| format!("{:.2} {}", a, currency))
+                .unwrap_or_default(),
+        ]);
+        match totals.iter_mut().find(|(c, _, _)| *c == currency) {
+            Some((_, minutes, amount)) => {
+                *minutes += line.billed_minutes;
+                *amount += line.amount.unwrap_or(0.0);
+            }
+            None => totals.push((currency, line.billed_minutes, line.amount.unwrap_or(0.0))),
+        }
+    }
+    println!("{table}");
+
+    println!("--:--");
+    for (currency, minutes, amount) in totals {
+        println!(
+            "{} billable, {:.2} {}",
+            format_duration(minutes, options.hours_per_day),
+            amount,
+            currency
+        );
+    }
+}
+
+/// Prints a matrix with one row per tag and one column per period
+/// (day, week or month) of the range, plus the totals of each.
+fn time_spent_per_period(
+    origins: &[OriginPlans],
+    from_inclusive: &chrono::NaiveDate,
+    to_inclusive: &chrono::NaiveDate,
+    group_by: GroupBy,
+    options: &SpentOptions,
+) {
+    let periods = group_by.periods(from_inclusive, to_inclusive);
+
+    let origins_per_period: Vec<Vec<OriginPlans>> = periods
+        .iter()
+        .map(|(start, end)| origins.iter().map(|o| o.between(start, end)).collect())
+        .collect();
+    let timings_per_period: Vec<Vec<time_summary::TagTime>> = origins_per_period
+        .iter()
+        .map(|period_origins| tag_timings(period_origins, options))
+        .collect();
+    let overall_timings = tag_timings(origins, options);
+
+    let mut table = Table::new();
+    let mut header = vec![
+        if options.combined {
+            "Origin / Tag"
+        } else {
+            "Tag"
+        }
+        .to_string(),
+    ];
+    header.extend(periods.iter().map(|(start, _)| group_by.label(start)));
+    header.push("Total".to_string());
+    table.set_header(header);
+    table.load_preset(presets::UTF8_FULL_CONDENSED);
+
+    add_period_rows_for_tagl(
+        &overall_timings,
+        &timings_per_period,
+        &mut table,
+        &mut Vec::new(),
+        options,
     );
+
+    let mut totals_row = vec!["Total".to_string()];
+    totals_row.extend(origins_per_period.iter().map(|period_origins| {
+        format_duration(
+            total_time_spent(period_origins, options),
+            options.hours_per_day,
+        )
+    }));
+    totals_row.push(format_duration(
+        total_time_spent(origins, options),
+        options.hours_per_day,
+    ));
+    table.add_row(totals_row);
+
+    println!("{table}");
+}
+
+fn add_period_rows_for_tagl(
+    tag_timings: &Vec<time_summary::TagTime>,
+    timings_per_period: &Vec<Vec<time_summary::TagTime>>,
+    table: &mut Table,
+    path: &mut Vec<String>,
+    options: &SpentOptions,
+) {
+    for tt in tag_timings {
+        path.push(tt.tag.clone());
+
+        let mut rowc = vec![format!("{}{}", "  ".repeat(path.len() - 1), tt.tag)];
+        for timings in timings_per_period {
+            match time_summary::find_tag_time(timings, path) {
+                Some(period_tt) => {
+                    rowc.push(format_duration(period_tt.minutes, options.hours_per_day))
+                }
+                None => rowc.push("".to_string()),
+            }
+        }
+        rowc.push(format_duration(tt.minutes, options.hours_per_day));
+        table.add_row(rowc);
+
+        add_period_rows_for_tagl(&tt.sub_tags, timings_per_period, table, path, options);
+        path.pop();
+    }
+}
+
+fn original_blocks(day_plans: &[DayPlan]) -> Vec<&Block> {
+    day_plans
+        .iter()
+        .flat_map(|dp| dp.only_original_blocks_slice())
+        .collect()
 }
 
-fn add_row_for_tagl(tag_timings: &Vec<time_summary::TagTime>, table: &mut Table, level: u8) {
+fn add_row_for_tagl(
+    tag_timings: &Vec<time_summary::TagTime>,
+    table: &mut Table,
+    level: u8,
+    tag_columns: u8,
+    options: &SpentOptions,
+) {
     let total = tag_timings.iter().fold(0, |acc, tt| acc + tt.minutes);
 
     for tt in tag_timings {
@@ -68,21 +438,172 @@ fn add_row_for_tagl(tag_timings: &Vec<time_summary::TagTime>, table: &mut Table,
             rowc.push("".to_string());
         }
         rowc.push(tt.tag.clone());
-        for _ in level..2 {
+        for _ in level..tag_columns - 1 {
             rowc.push("".to_string());
         }
         // time
-        let (hours, minutes) = minutes_to_hours_minutes(tt.minutes);
-        rowc.push(format!("{:02}:{:02}", hours, minutes));
+        rowc.push(format_duration(tt.minutes, options.hours_per_day));
 
         // %
-        rowc.push(format!("{:3}%", (tt.minutes as u32 * 100) / total as u32));
+        let percent = if total > 0 {
+            (tt.minutes as u64 * 100) / total as u64
+        } else {
+            0
+        };
+        rowc.push(format!("{:3}%", percent));
 
         table.add_row(rowc);
-        add_row_for_tagl(&tt.sub_tags, table, level + 1);
+        add_row_for_tagl(&tt.sub_tags, table, level + 1, tag_columns, options);
 
         if level == 0 {
             table.add_row(vec![""]);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day_plan::Source;
+    use crate::time_summary::find_tag_time;
+
+    fn origin(name: &str, blocks: Vec<Block>) -> OriginPlans {
+        OriginPlans {
+            name: name.to_string(),
+            day_plans: vec![DayPlan {
+                origin: name.to_string(),
+                blocks,
+                day: None,
+                source: Source::ICalendar,
+            }],
+        }
+    }
+
+    fn minutes_of(timings: &[time_summary::TagTime], path: &[&str]) -> Option<u32> {
+        let path: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+        find_tag_time(timings, &path).map(|tt| tt.minutes)
+    }
+
+    #[test]
+    fn test_combined_groups_by_origin_and_skips_synced_copies() {
+        // Each origin holds a synced copy of the other's block
+        let origins = vec![
+            origin(
+                "Work",
+                vec![
+                    Block::new("09:00 - 11:00", "Work", "Alpha @proj/alpha"),
+                    Block::new("18:00 - 19:00", "Home", "Gym @sport"),
+                ],
+            ),
+            origin(
+                "Home",
+                vec![
+                    Block::new("18:00 - 19:00", "Home", "Gym @sport"),
+                    Block::new("09:00 - 11:00", "Work", "Alpha @proj/alpha"),
+                ],
+            ),
+        ];
+        let options = SpentOptions {
+            combined: true,
+            ..Default::default()
+        };
+
+        let timings = tag_timings(&origins, &options);
+
+        let top_level: Vec<&str> = timings.iter().map(|tt| tt.tag.as_str()).collect();
+        assert_eq!(top_level, vec!["Work", "Home"]);
+        assert_eq!(minutes_of(&timings, &["Work"]), Some(120));
+        assert_eq!(minutes_of(&timings, &["Work", "proj", "alpha"]), Some(120));
+        assert_eq!(minutes_of(&timings, &["Work", "sport"]), None);
+        assert_eq!(minutes_of(&timings, &["Home"]), Some(60));
+        assert_eq!(minutes_of(&timings, &["Home", "sport"]), Some(60));
+        assert_eq!(minutes_of(&timings, &["Home", "proj"]), None);
+        assert_eq!(total_time_spent(&origins, &options), 180);
+    }
+
+    #[test]
+    fn test_combined_with_an_empty_origin() {
+        let origins = vec![
+            origin("Work", vec![Block::new("09:00 - 10:00", "Work", "@proj")]),
+            origin("Home", vec![]),
+        ];
+        let options = SpentOptions {
+            combined: true,
+            ..Default::default()
+        };
+
+        let timings = tag_timings(&origins, &options);
+
+        assert_eq!(minutes_of(&timings, &["Work", "proj"]), Some(60));
+        assert_eq!(minutes_of(&timings, &["Home"]), Some(0));
+        assert!(
+            find_tag_time(&timings, &["Home".to_string()])
+                .unwrap()
+                .sub_tags
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_tag_filter_matches_the_unfiltered_report() {
+        let origins = vec![origin(
+            "Work",
+            vec![
+                Block::new("09:00 - 11:00", "Work", "Kickoff @client/acme @meetings"),
+                Block::new(
+                    "11:00 - 12:00",
+                    "Work",
+                    "Review @client/acme:25% @meetings:75%",
+                ),
+                Block::new(
+                    "13:00 - 14:00",
+                    "Work",
+                    "Tickets @mail @client/acme/support",
+                ),
+                Block::new("14:00 - 15:00", "Work", "Gym @sport"),
+            ],
+        )];
+
+        for (allocation, acme_minutes) in [
+            (Allocation::Full, 240),
+            (Allocation::Split, 120),
+            (Allocation::First, 180),
+            (Allocation::Weighted, 105),
+        ] {
+            let unfiltered = tag_timings(
+                &origins,
+                &SpentOptions {
+                    allocation,
+                    ..Default::default()
+                },
+            );
+            let options = SpentOptions {
+                allocation,
+                tag_filters: vec!["client/acme".parse().unwrap()],
+                ..Default::default()
+            };
+            let filtered = tag_timings(&origins, &options);
+
+            assert_eq!(
+                minutes_of(&unfiltered, &["client", "acme"]),
+                Some(acme_minutes),
+                "{:?}",
+                allocation
+            );
+            assert_eq!(
+                minutes_of(&filtered, &["client/acme"]),
+                Some(acme_minutes),
+                "{:?}",
+                allocation
+            );
+            assert_eq!(
+                minutes_of(&filtered, &["client/acme", "support"]),
+                minutes_of(&unfiltered, &["client", "acme", "support"]),
+                "{:?}",
+                allocation
+            );
+            assert_eq!(filtered.len(), 1, "{:?}", allocation);
+            assert_eq!(total_time_spent(&origins, &options), acme_minutes);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use chrono::NaiveDate;
+use comfy_table::Table;
+use comfy_table::presets;
+use std::collections::BTreeMap;
+
+use crate::block::Block;
+use crate::blockary_cfg;
+use crate::day_plan::{DayPlan, Source};
+use crate::sync::Sync;
+use crate::tag::{Tag, parse_tags, rewrite_tags};
+use crate::time_summary::format_duration;
+
+/// Tags whose names differ by at most this many edits are reported as
+/// possible duplicates
+const MAX_TYPO_DISTANCE: usize = 2;
+
+/// Usage of one tag path, e.g. `@p/alpha`, including all of its sub-tags
+#[derive(Debug, Default, PartialEq, Eq)]
+struct TagStats {
+    blocks: u32,
+    minutes: u32,
+    first_seen: Option<NaiveDate>,
+    last_seen: Option<NaiveDate>,
+}
+
+impl TagStats {
+    fn add(&mut self, block: &Block, day: Option<NaiveDate>) {
+        self.blocks += 1;
+        self.minutes += block.duration;
+        if let Some(day) = day {
+            self.first_seen = Some(self.first_seen.map_or(day, |d| d.min(day)));
+            self.last_seen = Some(self.last_seen.map_or(day, |d| d.max(day)));
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Similarity {
+    Case,
+    Typo,
+}
+
+pub fn list(config: &blockary_cfg::Config, hours_per_day: Option<u32>) {
+    let sync = Sync::from_config(config);
+    let stats = tag_stats(&sync.all_day_plans());
+    if stats.is_empty() {
+        println!("No tags found");
+        return;
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["Tag", "Blocks", "First seen", "Last seen", "Time"]);
+    table.load_preset(presets::UTF8_FULL_CONDENSED);
+    for (tagls, stat) in &stats {
+        let indent = "  ".repeat(tagls.len() - 1);
+        let date = |d: Option<NaiveDate>| d.map(|d| d.to_string()).unwrap_or_default();
+        table.add_row(vec![
+            format!("{}{}", indent, tagls.last().unwrap()),
+            stat.blocks.to_string(),
+            date(stat.first_seen),
+            date(stat.last_seen),
+            format_duration(stat.minutes, hours_per_day),
+        ]);
+    }
+    println!("{table}");
+
+    let duplicates = near_duplicates(&stats);
+    if !duplicates.is_empty() {
+        println!("\nPossible duplicates:");
+        for (a, b, similarity) in duplicates {
+            let reason = match similarity {
+                Similarity::Case => "differs in case",
+                Similarity::Typo => "similar spelling",
+            };
+            println!("  @{}  ~  @{}  ({})", a.join("/"), b.join("/"), reason);
+        }
+    }
+}
+
+/// Collects the usage of every tag path in the original blocks of the
+/// plans, so blocks synced into several directories are counted once.
+/// A block counts once towards each prefix of its tags, e.g. `@p/a/x`
+/// counts for `@p`, `@p/a` and `@p/a/x`.
+fn tag_stats(day_plans: &[DayPlan]) -> BTreeMap<Vec<String>, TagStats> {
+    let mut stats: BTreeMap<Vec<String>, TagStats> = BTreeMap::new();
+    for plan in day_plans {
+        let day = plan.day();
+        for block in plan.only_original_blocks_slice() {
+            let mut paths: Vec<&[String]> = block
+                .tags
+                .iter()
+                .flat_map(|tag| (1..=tag.tagls.len()).map(|n| &tag.tagls[..n]))
+                .collect();
+            paths.sort();
+            paths.dedup();
+            for path in paths {
+                stats.entry(path.to_vec()).or_default().add(block, day);
+            }
+        }
+    }
+    stats
+}
+
+/// Finds sibling tags whose names only differ in case or by a few
+/// characters, e.g. `@meeting` and `@meetings`
+fn near_duplicates(
+    stats: &BTreeMap<Vec<String>, TagStats>,
+) -> Vec<(&Vec<String>, &Vec<String>, Similarity)> {
+    let paths: Vec<&Vec<String>> = stats.keys().collect();
+    let mut duplicates = Vec::new();
+    for (i, a) in paths.iter().enumerate() {
+        for b in &paths[i + 1..] {
+            let (Some((name_a, parent_a)), Some((name_b, parent_b))) =
+                (a.split_last(), b.split_last())
+            else {
+                continue;
+            };
+            if parent_a != parent_b {
+                continue;
+            }
+            let (name_a, name_b) = (name_a.to_lowercase(), name_b.to_lowercase());
+            if name_a == name_b {
+                duplicates.push((*a, *b, Similarity::Case));
+            } else if name_a.chars().count().min(name_b.chars().count()) > MAX_TYPO_DISTANCE
+                && levenshtein(&name_a, &name_b) <= MAX_TYPO_DISTANCE
+            {
+                duplicates.push((*a, *b, Similarity::Typo));
+            }
+        }
+    }
+    duplicates
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+pub fn normalize(config: &blockary_cfg::Config, dry_run: bool) {
+    let aliases = &config.tags.aliases;
+    rewrite_vault(config, |tag| aliases.canonicalize(tag), dry_run);
+}
+
+/// Renames `from` and all of its sub-tags to `to`, e.g. `@p/alpha/x`
+/// becomes `@p/[[Project Alpha]]/x` when renaming `p/alpha`.
+pub fn rename(config: &blockary_cfg::Config, from: &Tag, to: &Tag, dry_run: bool) {
+    rewrite_vault(
+        config,
+        |tag| tag.with_replaced_prefix(&from.tagls, &to.tagls),
+        dry_run,
+    );
+}
+
+/// Rewrites the tags of all blocks in all configured directories for which
+/// `rewrite` returns a new tag, printing each changed block as a diff.
+/// With `dry_run`, no file is written.
+fn rewrite_vault(
+    config: &blockary_cfg::Config,
+    rewrite: impl Fn(&Tag) -> Option<Tag>,
+    dry_run: bool,
+) {
+    let sync = Sync::from_config(config);
+    let mut changed_blocks = 0;
+    let mut changed_files = 0;
+
+    for plan in sync.all_day_plans() {
+        let mut changed = false;
+        let blocks = plan
+            .blocks
+            .iter()
+            .map(|b| {
+                let desc = rewrite_tags(&b.desc, &rewrite);
+                if desc == b.desc {
+                    return b.clone();
+                }
+                if !changed {
+                    println!("\n{}", file_name(&plan));
+                    changed = true;
+                }
+                let block = Block {
+                    tags: parse_tags(&desc),
+                    desc,
+                    ..b.clone()
+                };
+                println!("- {}", b.to_block_string(b.origin != plan.origin));
+                println!("+ {}", block.to_block_string(block.origin != plan.origin));
+                changed_blocks += 1;
+                block
+            })
+            .collect();
+
+        if changed {
+            changed_files += 1;
+            if !dry_run {
+                DayPlan { blocks, ..plan }.write_to_daily_file();
+            }
+        }
+    }
+
+    if dry_run {
+        println!("\n{changed_blocks} blocks in {changed_files} files would be changed (dry run)");
+    } else {
+        println!("\n{changed_blocks} blocks in {changed_files} files changed");
+    }
+}
+
+fn file_name(plan: &DayPlan) -> String {
+    match &plan.source {
+        Source::ObsMarkDown { abs_path, .. } => abs_path.clone(),
+        Source::ICalendar => plan.origin.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn plan(origin: &str, day: u32, blocks: Vec<Block>) -> DayPlan {
+        DayPlan {
+            origin: origin.to_string(),
+            blocks,
+            day: NaiveDate::from_ymd_opt(2025, 3, day),
+            source: Source::ICalendar,
+        }
+    }
+
+    fn path(tag: &str) -> Vec<String> {
+        tag.split('/').map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tag_stats_count_each_prefix_once_per_block() {
+        let plans = vec![
+            plan(
+                "work",
+                10,
+                vec![
+                    Block::new("09:00 - 10:00", "work", "@p/a @p/b"),
+                    Block::new("10:00 - 10:30", "work", "@p/a/x"),
+                ],
+            ),
+            plan(
+                "home",
+                12,
+                vec![
+                    Block::new("09:00 - 10:00", "work", "@p/a synced copy"),
+                    Block::new("11:00 - 12:00", "home", "@p/a"),
+                ],
+            ),
+        ];
+
+        let stats = tag_stats(&plans);
+
+        assert_eq!(
+            stats[&path("p")],
+            TagStats {
+                blocks: 3,
+                minutes: 150,
+                first_seen: NaiveDate::from_ymd_opt(2025, 3, 10),
+                last_seen: NaiveDate::from_ymd_opt(2025, 3, 12),
+            }
+        );
+        assert_eq!(stats[&path("p/a")].blocks, 3);
+        assert_eq!(stats[&path("p/b")].minutes, 60);
+        assert_eq!(stats[&path("p/a/x")].blocks, 1);
+        let order: Vec<&Vec<String>> = stats.keys().collect();
+        assert_eq!(
+            order,
+            vec![&path("p"), &path("p/a"), &path("p/a/x"), &path("p/b")]
+        );
+    }
+
+    #[test]
+    fn test_near_duplicates() {
+        let blocks = vec![Block::new(
+            "09:00 - 10:00",
+            "work",
+            "@meetings @meeting @Meetings @p/ab @p/ac @dev/review @p/review",
+        )];
+        let stats = tag_stats(&[plan("work", 10, blocks)]);
+
+        let duplicates = near_duplicates(&stats);
+
+        assert_eq!(
+            duplicates,
+            vec![
+                (&path("Meetings"), &path("meeting"), Similarity::Typo),
+                (&path("Meetings"), &path("meetings"), Similarity::Case),
+                (&path("meeting"), &path("meetings"), Similarity::Typo),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_rewrites_the_vault_unless_dry_run() {
+        let root = std::env::temp_dir().join(format!("blockary-rename-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let note = root.join("2025-03-10.md");
+        let content = "# Monday\n\n## Time Blocks\n\
+            - 09:00 - 10:00 Review @p/alpha/design:50% @mtg:50%\n\
+            - 10:00 - 11:00 Spelling @p/alphabet\n";
+        fs::write(&note, content).unwrap();
+        let config = blockary_cfg::load(&format!(
+            "[dirs.work]\nname = \"Work\"\npath = \"{}\"\n",
+            root.display()
+        ));
+        let from: Tag = "p/alpha".parse().unwrap();
+        let to: Tag = "@p/[[Project Alpha]]".parse().unwrap();
+
+        rename(&config, &from, &to, true);
+        let after_dry_run = fs::read_to_string(&note).unwrap();
+        rename(&config, &from, &to, false);
+        let after_rename = fs::read_to_string(&note).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(after_dry_run, content);
+        assert!(
+            after_rename
+                .contains("- 09:00 - 10:00 Review @p/[[Project Alpha]]/design:50% @mtg:50%\n")
+        );
+        assert!(after_rename.contains("- 10:00 - 11:00 Spelling @p/alphabet\n"));
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("meeting", "meetings"), 1);
+        assert_eq!(levenshtein("review", "reveiw"), 2);
+        assert_eq!(levenshtein("alpha", "alpha"), 0);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+}
@@ -4,7 +4,12 @@ use chrono::NaiveDate;
 use regex::Regex;
 use walkdir::WalkDir;
 
-use crate::{block::Block, cal_day_plan::day_plans_from_ical, markdown_access};
+use crate::{
+    block::Block,
+    cal_day_plan::{EventFormat, day_plans_from_ical},
+    cal_filter::EventFilter,
+    markdown_access,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DayPlanRepoType {
@@ -38,7 +43,16 @@ impl DayPlanRepo {
             DayPlanRepoType::MarkdownDirectory { dir } => {
                 day_plans_from_md_directory(&self.name, &dir)
             }
-            DayPlanRepoType::ICalString { ical } => day_plans_from_ical(ical, &self.name),
+            DayPlanRepoType::ICalString { ical } => day_plans_from_ical(
+                ical,
+                &self.name,
+                &EventFormat::default(),
+                &EventFilter::default(),
+            )
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                Vec::new()
+            }),
         }
     }
 
@@ -1,14 +1,26 @@
+mod billing;
 mod block;
 mod blockary_cfg;
+mod cal_cache;
 mod cal_day_plan;
+mod cal_fetch;
+mod cal_filter;
 mod cli;
+mod cmd_export;
+mod cmd_export_ics;
+mod cmd_import;
+mod cmd_invoice;
 mod cmd_pull;
+mod cmd_push;
+mod cmd_serve;
 mod cmd_spent;
 mod cmd_sync;
+mod cmd_tags;
 mod day_plan;
 mod markdown_access;
 mod sync;
 mod tag;
+mod time_range;
 mod time_summary;
 
 fn main() {
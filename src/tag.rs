@@ -1,19 +1,253 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Tag {
     pub tagls: Vec<String>,
+    /// Share of the block's time in percent, given as `@tag:60%`
+    pub weight: Option<u32>,
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.tagls.join("/"))?;
+        if let Some(weight) = self.weight {
+            write!(f, ":{}%", weight)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Tag {
+    type Err = String;
+
+    /// Parses a single tag without a weight, with or without the leading
+    /// `@`. The whole input must be that tag, so levels with spaces need
+    /// to be wrapped, e.g. `p/[[Project Alpha]]`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let input = input.strip_prefix('@').unwrap_or(input);
+        let tag_str = format!("@{}", input);
+        match parse_tag_spans(&tag_str).as_slice() {
+            [(span, tag)]
+                if *span == (0..tag_str.len()) && tag.tagls.iter().all(|l| is_closed(l)) =>
+            {
+                match tag.weight {
+                    Some(_) => Err(format!("'{}' must not have a weight", input)),
+                    None => Ok(tag.clone()),
+                }
+            }
+            _ => Err(format!("'{}' is not a valid tag", input)),
+        }
+    }
+}
+
+impl Tag {
+    /// Replaces the levels `from` at the start of this tag with `to`.
+    /// Returns `None` if the tag does not start with `from`.
+    pub fn with_replaced_prefix(&self, from: &[String], to: &[String]) -> Option<Tag> {
+        if !self.tagls.starts_with(from) {
+            return None;
+        }
+        let mut tagls = to.to_vec();
+        tagls.extend_from_slice(&self.tagls[from.len()..]);
+        Some(Tag {
+            tagls,
+            weight: self.weight,
+        })
+    }
+}
+
+/// Maps alternative spellings of tags to their canonical form, e.g.
+/// `mtg` to `meetings`. Aliases apply hierarchically, so `mtg/standup`
+/// becomes `meetings/standup`. If several aliases match, the one with
+/// the most levels wins.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize)]
+#[serde(try_from = "HashMap<String, String>")]
+pub struct TagAliases {
+    aliases: Vec<(Vec<String>, Vec<String>)>,
+}
+
+impl TryFrom<HashMap<String, String>> for TagAliases {
+    type Error = String;
+
+    fn try_from(map: HashMap<String, String>) -> Result<Self, Self::Error> {
+        let mut aliases = Vec::new();
+        for (alias, canonical) in map {
+            let alias: Tag = alias.parse()?;
+            let canonical: Tag = canonical.parse()?;
+            aliases.push((alias.tagls, canonical.tagls));
+        }
+        aliases.sort_by_key(|(alias, _)| std::cmp::Reverse(alias.len()));
+        Ok(TagAliases { aliases })
+    }
+}
+
+impl TagAliases {
+    /// Returns the canonical form of `tag`, or `None` if it has no alias.
+    pub fn canonicalize(&self, tag: &Tag) -> Option<Tag> {
+        self.aliases
+            .iter()
+            .find_map(|(alias, canonical)| tag.with_replaced_prefix(alias, canonical))
+    }
+
+    pub fn canonicalize_all(&self, tags: &[Tag]) -> Vec<Tag> {
+        tags.iter()
+            .map(|tag| self.canonicalize(tag).unwrap_or_else(|| tag.clone()))
+            .collect()
+    }
+}
+
+/// A pattern over the levels of a tag, e.g. `break`, `private/*` or
+/// `client/acme-*`. A `*` matches any sequence of characters within a
+/// single level. Patterns match hierarchically: `client` matches
+/// `@client` as well as `@client/acme/internal`, while `private/*` only
+/// matches tags below `@private`.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TagPattern {
+    pub levels: Vec<String>,
+}
+
+impl FromStr for TagPattern {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.parse::<Tag>() {
+            Ok(tag) => Ok(TagPattern { levels: tag.tagls }),
+            Err(_) => Err(format!("'{}' is not a valid tag pattern", input.trim())),
+        }
+    }
+}
+
+impl TryFrom<String> for TagPattern {
+    type Error = String;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        input.parse()
+    }
+}
+
+impl TagPattern {
+    pub fn matches(&self, tag: &Tag) -> bool {
+        self.levels.len() <= tag.tagls.len()
+            && self
+                .levels
+                .iter()
+                .zip(&tag.tagls)
+                .all(|(pattern, tagl)| glob_matches(pattern, tagl))
+    }
+}
+
+/// A query over tags, e.g. `client/acme` or `!client/acme/internal`. A
+/// leading `!` negates the pattern.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TagFilter {
+    Matching(TagPattern),
+    NotMatching(TagPattern),
+}
+
+impl FromStr for TagFilter {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().strip_prefix('!') {
+            Some(negated) => Ok(TagFilter::NotMatching(negated.parse()?)),
+            None => Ok(TagFilter::Matching(input.parse()?)),
+        }
+    }
+}
+
+/// Restricts the tags of blocks to those matching all `filters`, see
+/// `filter_tag`.
+pub fn filter_tags(tags: &[Tag], filters: &[TagFilter]) -> Vec<Tag> {
+    tags.iter()
+        .filter_map(|tag| filter_tag(tag, filters))
+        .collect()
+}
+
+/// Returns `tag` if it matches all `filters`: it must match one of the
+/// positive patterns (if there are any) and none of the negated ones.
+/// Tags matched by a positive pattern are re-rooted at the matched
+/// levels, so with `client/acme`, `@client/acme/support` becomes
+/// `@(client/acme)/support`.
+pub fn filter_tag(tag: &Tag, filters: &[TagFilter]) -> Option<Tag> {
+    let mut positive = filters.iter().filter_map(|f| match f {
+        TagFilter::Matching(p) => Some(p),
+        _ => None,
+    });
+    let mut negative = filters.iter().filter_map(|f| match f {
+        TagFilter::NotMatching(p) => Some(p),
+        _ => None,
+    });
+
+    if negative.any(|p| p.matches(tag)) {
+        return None;
+    }
+    if !filters.iter().any(|f| matches!(f, TagFilter::Matching(_))) {
+        return Some(tag.clone());
+    }
+    let pattern = positive.find(|p| p.matches(tag))?;
+    let root_levels = pattern.levels.len();
+    let mut tagls = vec![tag.tagls[..root_levels].join("/")];
+    tagls.extend_from_slice(&tag.tagls[root_levels..]);
+    Some(Tag {
+        tagls,
+        weight: tag.weight,
+    })
+}
+
+/// Matches `text` against `pattern`, where `*` stands for any sequence
+/// of characters (including none).
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Classic greedy wildcard matching with backtracking to the last `*`
+    let (mut p, mut t) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            last_star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = last_star {
+            p = star_p + 1;
+            t = star_t + 1;
+            last_star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
 pub fn parse_tags(input: &str) -> Vec<Tag> {
+    parse_tag_spans(input)
+        .into_iter()
+        .map(|(_, tag)| tag)
+        .collect()
+}
+
+/// Like `parse_tags`, but also returns the byte range of each tag in
+/// `input`, from the `@` up to and including a weight.
+pub fn parse_tag_spans(input: &str) -> Vec<(Range<usize>, Tag)> {
     let mut tags = Vec::new();
     let mut chars = input.char_indices().peekable();
 
-    while let Some((_, c)) = chars.next() {
+    while let Some((start, c)) = chars.next() {
         if c == '@' {
             // Check if there's at least one char after '+' and it's not whitespace
             if let Some(&(_, next_c)) = chars.peek() {
                 if !next_c.is_whitespace() {
                     if let Some(tag) = parse_single_tag(&mut chars) {
-                        tags.push(tag);
+                        let end = chars.peek().map(|(i, _)| *i).unwrap_or(input.len());
+                        tags.push((start..end, tag));
                     }
                 }
             }
@@ -22,8 +256,69 @@ pub fn parse_tags(input: &str) -> Vec<Tag> {
     tags
 }
 
+/// Whether a level that starts with `[[` or `(` is also closed again
+fn is_closed(level: &str) -> bool {
+    (!level.starts_with("[[") || level.ends_with("]]"))
+        && (!level.starts_with('(') || level.ends_with(')'))
+}
+
+/// The plain name of a tag level, without the `[[...]]` or `(...)`
+/// that allow spaces in it
+pub fn level_name(level: &str) -> &str {
+    level
+        .strip_prefix("[[")
+        .and_then(|l| l.strip_suffix("]]"))
+        .or_else(|| level.strip_prefix('(').and_then(|l| l.strip_suffix(')')))
+        .unwrap_or(level)
+}
+
+/// Turns a name into a tag level, wrapping it in `(...)` if it contains
+/// characters that would end the level, e.g. `(Project Alpha)`
+pub fn level_for_name(name: &str) -> String {
+    let name = name.trim();
+    let needs_wrapping = name
+        .chars()
+        .any(|c| c.is_whitespace() || "/@:()[]".contains(c));
+    if !needs_wrapping {
+        name.to_string()
+    } else if name.contains(')') {
+        format!("[[{}]]", name)
+    } else {
+        format!("({})", name)
+    }
+}
+
+/// Removes all tags from `input`, e.g. to get a plain description
+pub fn strip_tags(input: &str) -> String {
+    let mut output = String::new();
+    let mut last_end = 0;
+    for (span, _) in parse_tag_spans(input) {
+        output.push_str(&input[last_end..span.start]);
+        last_end = span.end;
+    }
+    output.push_str(&input[last_end..]);
+    output.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Replaces every tag in `input` for which `rewrite` returns a new tag,
+/// leaving the rest of the text untouched.
+pub fn rewrite_tags(input: &str, rewrite: impl Fn(&Tag) -> Option<Tag>) -> String {
+    let mut output = String::new();
+    let mut last_end = 0;
+    for (span, tag) in parse_tag_spans(input) {
+        if let Some(new_tag) = rewrite(&tag) {
+            output.push_str(&input[last_end..span.start]);
+            output.push_str(&new_tag.to_string());
+            last_end = span.end;
+        }
+    }
+    output.push_str(&input[last_end..]);
+    output
+}
+
 fn parse_single_tag(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> Option<Tag> {
     let mut levels = Vec::new();
+    let mut weight = None;
 
     loop {
         let mut current_level = String::new();
@@ -61,6 +356,12 @@ fn parse_single_tag(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> O
                     if c == '/' || c.is_whitespace() || c == '@' {
                         break;
                     }
+                    if c == ':' {
+                        weight = parse_weight(chars);
+                        if weight.is_some() {
+                            break;
+                        }
+                    }
                     current_level.push(chars.next().unwrap().1);
                 }
             }
@@ -70,6 +371,14 @@ fn parse_single_tag(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> O
             levels.push(current_level);
         }
 
+        // A weight always ends the tag
+        if weight.is_none() {
+            weight = parse_weight(chars);
+        }
+        if weight.is_some() {
+            break;
+        }
+
         // Check if there is a next level segment
         if let Some(&(_, '/')) = chars.peek() {
             chars.next(); // consume '/'
@@ -82,8 +391,35 @@ fn parse_single_tag(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> O
     if levels.is_empty() {
         None
     } else {
-        Some(Tag { tagls: levels })
+        Some(Tag {
+            tagls: levels,
+            weight,
+        })
+    }
+}
+
+/// Consumes a weight suffix like `:60%` and returns the percentage. If
+/// the next characters are no such suffix or the percentage is above 100,
+/// nothing is consumed.
+fn parse_weight(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> Option<u32> {
+    let mut lookahead = chars.clone();
+    if lookahead.next()?.1 != ':' {
+        return None;
+    }
+    let mut digits = String::new();
+    while let Some(&(_, c)) = lookahead.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        lookahead.next();
+    }
+    if lookahead.next()?.1 != '%' {
+        return None;
     }
+    let weight = digits.parse().ok().filter(|&w| w <= 100)?;
+    *chars = lookahead;
+    Some(weight)
 }
 
 #[cfg(test)]
@@ -133,6 +469,234 @@ mod tests {
         assert_eq!(result[1].tagls, vec!["p", "[[Project X]]"]);
     }
 
+    fn tag(input: &str) -> Tag {
+        parse_tags(input).remove(0)
+    }
+
+    #[test]
+    fn test_pattern_matches_hierarchically() {
+        let pattern: TagPattern = "client/acme".parse().unwrap();
+
+        assert!(pattern.matches(&tag("@client/acme")));
+        assert!(pattern.matches(&tag("@client/acme/internal")));
+        assert!(!pattern.matches(&tag("@client")));
+        assert!(!pattern.matches(&tag("@client/acme-corp")));
+        assert!(!pattern.matches(&tag("@other/client/acme")));
+    }
+
+    #[test]
+    fn test_pattern_with_wildcards() {
+        let below_private: TagPattern = "@private/*".parse().unwrap();
+        assert!(below_private.matches(&tag("@private/health")));
+        assert!(below_private.matches(&tag("@private/health/doctor")));
+        assert!(!below_private.matches(&tag("@private")));
+
+        let prefixed: TagPattern = "p/[[PROJ *]]".parse().unwrap();
+        assert!(prefixed.matches(&tag("@p/[[PROJ Vancouver]]")));
+        assert!(!prefixed.matches(&tag("@p/[[Vancouver]]")));
+
+        let infix: TagPattern = "*-ops*".parse().unwrap();
+        assert!(infix.matches(&tag("@team-ops")));
+        assert!(infix.matches(&tag("@dev-ops-oncall")));
+        assert!(!infix.matches(&tag("@ops")));
+    }
+
+    #[test]
+    fn test_filter_tags_reroots_at_matched_levels() {
+        let tags = parse_tags("@client/acme/support @meetings @client/other");
+        let filters = vec!["client/acme".parse().unwrap()];
+
+        let filtered = filter_tags(&tags, &filters);
+
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].tagls, vec!["client/acme", "support"]);
+    }
+
+    #[test]
+    fn test_filter_tags_with_wildcards_and_negation() {
+        let filters: Vec<TagFilter> = vec![
+            "client/*".parse().unwrap(),
+            "!client/acme/internal".parse().unwrap(),
+        ];
+
+        let filtered = filter_tags(
+            &parse_tags("@client/acme/internal @client/acme/support @client/other @meetings"),
+            &filters,
+        );
+
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered[0].tagls, vec!["client/acme", "support"]);
+        assert_eq!(filtered[1].tagls, vec!["client/other"]);
+    }
+
+    #[test]
+    fn test_filter_tags_only_negated() {
+        let filters: Vec<TagFilter> = vec!["!meetings".parse().unwrap()];
+
+        let filtered = filter_tags(&parse_tags("@proj/a @meetings/standup"), &filters);
+
+        assert_eq!(filtered, parse_tags("@proj/a"));
+    }
+
+    #[test]
+    fn test_tag_to_string() {
+        assert_eq!(
+            tag("@p/[[PROJ X]]/(a b)").to_string(),
+            "@p/[[PROJ X]]/(a b)"
+        );
+        assert_eq!(tag("@meetings:40%").to_string(), "@meetings:40%");
+    }
+
+    #[test]
+    fn test_tag_spans() {
+        let input = "Call @p/[[Big Co]]:50% and @mtg, then lunch";
+        let spans = parse_tag_spans(input);
+
+        assert_eq!(&input[spans[0].0.clone()], "@p/[[Big Co]]:50%");
+        assert_eq!(&input[spans[1].0.clone()], "@mtg,");
+    }
+
+    #[test]
+    fn test_rewrite_tags() {
+        let rewritten = rewrite_tags("Call @mtg/daily and @proj @mtg:20%", |tag| {
+            tag.with_replaced_prefix(&["mtg".to_string()], &["meetings".to_string()])
+        });
+
+        assert_eq!(rewritten, "Call @meetings/daily and @proj @meetings:20%");
+    }
+
+    #[test]
+    fn test_rename_keeps_bracketed_levels_and_weights() {
+        let from: Tag = "p/alpha".parse().unwrap();
+        let to: Tag = "@p/[[Project Alpha]]".parse().unwrap();
+
+        let rewritten = rewrite_tags(
+            "Review @p/alpha/(code review):30% @p/alphabet @p/[[alpha]] @x/p/alpha",
+            |tag| tag.with_replaced_prefix(&from.tagls, &to.tagls),
+        );
+
+        assert_eq!(
+            rewritten,
+            "Review @p/[[Project Alpha]]/(code review):30% @p/alphabet @p/[[alpha]] @x/p/alpha"
+        );
+    }
+
+    #[test]
+    fn test_strip_tags() {
+        assert_eq!(
+            strip_tags("Review @p/[[Big Co]]:50% with [[Lars]] @mtg"),
+            "Review with [[Lars]]"
+        );
+        assert_eq!(strip_tags("@only/tags"), "");
+    }
+
+    #[test]
+    fn test_level_name() {
+        assert_eq!(level_name("[[Project Alpha]]"), "Project Alpha");
+        assert_eq!(level_name("(code review)"), "code review");
+        assert_eq!(level_name("alpha"), "alpha");
+    }
+
+    #[test]
+    fn test_level_for_name_round_trips() {
+        for name in ["acme", "Project Alpha", "R&D / Ops", "Tools (internal)"] {
+            let tag = tag(&format!("@client/{} done", level_for_name(name)));
+            assert_eq!(tag.tagls.len(), 2);
+            assert_eq!(level_name(&tag.tagls[1]), name);
+        }
+    }
+
+    #[test]
+    fn test_aliases() {
+        let aliases = TagAliases::try_from(HashMap::from([
+            ("mtg".to_string(), "meetings".to_string()),
+            ("meeting".to_string(), "@meetings".to_string()),
+            ("p/alpha".to_string(), "p/[[Project Alpha]]".to_string()),
+            ("p/alpha/old".to_string(), "archive".to_string()),
+        ]))
+        .unwrap();
+
+        let canonical = |input: &str| aliases.canonicalize(&tag(input)).map(|t| t.to_string());
+
+        assert_eq!(canonical("@mtg"), Some("@meetings".to_string()));
+        assert_eq!(
+            canonical("@meeting/standup"),
+            Some("@meetings/standup".to_string())
+        );
+        assert_eq!(canonical("@meetings"), None);
+        assert_eq!(
+            canonical("@p/alpha/x"),
+            Some("@p/[[Project Alpha]]/x".to_string())
+        );
+        assert_eq!(canonical("@p/alpha/old/x"), Some("@archive/x".to_string()));
+        assert_eq!(canonical("@p/alphabet"), None);
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!("".parse::<TagPattern>().is_err());
+        assert!("@".parse::<TagPattern>().is_err());
+        assert!("client/acme internal".parse::<TagPattern>().is_err());
+        assert!("client/acme:50%".parse::<TagPattern>().is_err());
+    }
+
+    #[test]
+    fn test_parse_single_tag() {
+        let parsed = |input: &str| input.parse::<Tag>().map(|t| t.to_string());
+
+        assert_eq!(parsed("p/alpha"), Ok("@p/alpha".to_string()));
+        assert_eq!(parsed(" @p/alpha "), Ok("@p/alpha".to_string()));
+        assert_eq!(
+            parsed("@p/[[Project Alpha]]"),
+            Ok("@p/[[Project Alpha]]".to_string())
+        );
+        assert_eq!(
+            parsed("p/(code review)/x"),
+            Ok("@p/(code review)/x".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_single_tag_rejects_leftovers() {
+        assert!("project/Project Alpha".parse::<Tag>().is_err());
+        assert!("@p/alpha @p/beta".parse::<Tag>().is_err());
+        assert!("p/alpha:60%".parse::<Tag>().is_err());
+        assert!("p/[[Project Alpha".parse::<Tag>().is_err());
+        assert!("".parse::<Tag>().is_err());
+    }
+
+    #[test]
+    fn test_weights() {
+        let result = parse_tags("Review @proj/alpha:60% @meetings:40% @[[Big Co]]:10% @misc");
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].tagls, vec!["proj", "alpha"]);
+        assert_eq!(result[0].weight, Some(60));
+        assert_eq!(result[1].tagls, vec!["meetings"]);
+        assert_eq!(result[1].weight, Some(40));
+        assert_eq!(result[2].tagls, vec!["[[Big Co]]"]);
+        assert_eq!(result[2].weight, Some(10));
+        assert_eq!(result[3].weight, None);
+    }
+
+    #[test]
+    fn test_colon_without_weight_is_part_of_the_tag() {
+        let result = parse_tags("@time:box @half:50 @x:%");
+        assert_eq!(result[0].tagls, vec!["time:box"]);
+        assert_eq!(result[0].weight, None);
+        assert_eq!(result[1].tagls, vec!["half:50"]);
+        assert_eq!(result[2].tagls, vec!["x:%"]);
+    }
+
+    #[test]
+    fn test_weights_above_100_are_part_of_the_tag() {
+        let result = parse_tags("@a:100% @b:101% @c:4000000000%");
+        assert_eq!(result[0].weight, Some(100));
+        assert_eq!(result[1].tagls, vec!["b:101%"]);
+        assert_eq!(result[1].weight, None);
+        assert_eq!(result[2].tagls, vec!["c:4000000000%"]);
+        assert_eq!(result[2].weight, None);
+    }
+
     #[test]
     fn test_spaces_without_paranthesis() {
         let input = "@p/Project X";
@@ -0,0 +1,370 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use clap::ValueEnum;
+use regex::Regex;
+use std::str::FromStr;
+
+const LAST_DAYS_REGEX: &str = r"^last-(\d+)-days$";
+const QUARTER_REGEX: &str = r"^(\d{4})-q([1-4])$";
+const ISO_WEEK_REGEX: &str = r"^(\d{4})-w(\d{1,2})$";
+const MONTH_REGEX: &str = r"^(\d{4})-(\d{2})$";
+/// A hundred years, far more than any vault covers and far from the
+/// limits of `NaiveDate`
+const MAX_LAST_DAYS: u32 = 36_525;
+
+/// A named or relative period of days, e.g. `this-week`, `last-7-days`,
+/// `2025-Q1`, `2025-W11` or `2025-03`. Use [`TimeRange::bounds`] to turn it into
+/// an inclusive `(start, end)` pair relative to a given day.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimeRange {
+    Today,
+    Yesterday,
+    ThisWeek,
+    ThisMonth,
+    ThisYear,
+    LastWeek,
+    LastMonth,
+    LastYear,
+    LastDays(u32),
+    Quarter { year: i32, quarter: u32 },
+    IsoWeek { year: i32, week: u32 },
+    Month { year: i32, month: u32 },
+}
+
+impl FromStr for TimeRange {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim().to_lowercase();
+        match input.as_str() {
+            "today" => return Ok(TimeRange::Today),
+            "yesterday" => return Ok(TimeRange::Yesterday),
+            "this-week" => return Ok(TimeRange::ThisWeek),
+            "this-month" => return Ok(TimeRange::ThisMonth),
+            "this-year" => return Ok(TimeRange::ThisYear),
+            "last-week" => return Ok(TimeRange::LastWeek),
+            "last-month" => return Ok(TimeRange::LastMonth),
+            "last-year" => return Ok(TimeRange::LastYear),
+            _ => {}
+        }
+
+        if let Some(matches) = Regex::new(LAST_DAYS_REGEX).unwrap().captures(&input) {
+            let days: u32 = matches[1]
+                .parse()
+                .map_err(|_| format!("Too many days in '{}'", input))?;
+            if days == 0 {
+                return Err("The number of days must be at least 1".to_string());
+            }
+            if days > MAX_LAST_DAYS {
+                return Err(format!(
+                    "Too many days in '{}', at most {} are supported",
+                    input, MAX_LAST_DAYS
+                ));
+            }
+            return Ok(TimeRange::LastDays(days));
+        }
+
+        if let Some(matches) = Regex::new(QUARTER_REGEX).unwrap().captures(&input) {
+            return Ok(TimeRange::Quarter {
+                year: matches[1].parse().unwrap(),
+                quarter: matches[2].parse().unwrap(),
+            });
+        }
+
+        if let Some(matches) = Regex::new(ISO_WEEK_REGEX).unwrap().captures(&input) {
+            let year: i32 = matches[1].parse().unwrap();
+            let week: u32 = matches[2].parse().unwrap();
+            if NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).is_none() {
+                return Err(format!("{} has no week {}", year, week));
+            }
+            return Ok(TimeRange::IsoWeek { year, week });
+        }
+
+        if let Some(matches) = Regex::new(MONTH_REGEX).unwrap().captures(&input) {
+            let year: i32 = matches[1].parse().unwrap();
+            let month: u32 = matches[2].parse().unwrap();
+            if !(1..=12).contains(&month) {
+                return Err(format!("{} is not a month", month));
+            }
+            return Ok(TimeRange::Month { year, month });
+        }
+
+        Err(format!(
+            "Unknown period '{}'. Use today, yesterday, this-week, this-month, this-year, \
+             last-week, last-month, last-year, last-N-days, YYYY-QN, YYYY-WNN or YYYY-MM",
+            input
+        ))
+    }
+}
+
+impl TimeRange {
+    /// Returns the first and last day (both inclusive) of this range,
+    /// with relative ranges being resolved against `today`.
+    pub fn bounds(&self, today: &NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            TimeRange::Today => (*today, *today),
+            TimeRange::Yesterday => {
+                let yesterday = *today - Duration::days(1);
+                (yesterday, yesterday)
+            }
+            TimeRange::ThisWeek => get_week_bounds(today),
+            TimeRange::ThisMonth => get_month_bounds(today),
+            TimeRange::ThisYear => get_year_bounds(today),
+            TimeRange::LastWeek => get_week_bounds(&(*today - Duration::days(7))),
+            TimeRange::LastMonth => {
+                let (start_of_month, _) = get_month_bounds(today);
+                get_month_bounds(&(start_of_month - Duration::days(1)))
+            }
+            TimeRange::LastYear => {
+                get_year_bounds(&NaiveDate::from_ymd_opt(today.year() - 1, 1, 1).unwrap())
+            }
+            TimeRange::LastDays(days) => (*today - Duration::days(*days as i64 - 1), *today),
+            TimeRange::Quarter { year, quarter } => {
+                let first_month = (quarter - 1) * 3 + 1;
+                let start = NaiveDate::from_ymd_opt(*year, first_month, 1).unwrap();
+                let (_, end) = get_month_bounds(&(start + Duration::days(62)));
+                (start, end)
+            }
+            TimeRange::IsoWeek { year, week } => (
+                NaiveDate::from_isoywd_opt(*year, *week, Weekday::Mon).unwrap(),
+                NaiveDate::from_isoywd_opt(*year, *week, Weekday::Sun).unwrap(),
+            ),
+            TimeRange::Month { year, month } => {
+                get_month_bounds(&NaiveDate::from_ymd_opt(*year, *month, 1).unwrap())
+            }
+        }
+    }
+}
+
+/// The length of the periods a longer range is split into.
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq)]
+pub enum GroupBy {
+    Day,
+    Week,
+    Month,
+}
+
+impl GroupBy {
+    /// Splits the range between both dates (inclusive) into consecutive
+    /// periods. The first and last period are cut off at the range
+    /// boundaries, so they may be shorter than a full week or month.
+    pub fn periods(
+        &self,
+        from_inclusive: &NaiveDate,
+        to_inclusive: &NaiveDate,
+    ) -> Vec<(NaiveDate, NaiveDate)> {
+        let mut periods = Vec::new();
+        let mut start = *from_inclusive;
+        while start <= *to_inclusive {
+            let (_, end) = match self {
+                GroupBy::Day => (start, start),
+                GroupBy::Week => get_week_bounds(&start),
+                GroupBy::Month => get_month_bounds(&start),
+            };
+            periods.push((start, end.min(*to_inclusive)));
+            start = end + Duration::days(1);
+        }
+        periods
+    }
+
+    /// A short label for the period that starts with `start`
+    pub fn label(&self, start: &NaiveDate) -> String {
+        match self {
+            GroupBy::Day => start.format("%Y-%m-%d").to_string(),
+            GroupBy::Week => start.format("%G-W%V").to_string(),
+            GroupBy::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+fn get_week_bounds(date: &NaiveDate) -> (NaiveDate, NaiveDate) {
+    // .weekday().number_from_monday() returns 1 for Mon, 7 for Sun
+    // Subtracting (1-indexed value - 1) gives us the distance back to Monday
+    let days_from_monday = date.weekday().number_from_monday() - 1;
+    let start_of_week = *date - Duration::days(days_from_monday as i64);
+    let end_of_week = start_of_week + Duration::days(6);
+
+    (start_of_week, end_of_week)
+}
+
+fn get_month_bounds(date: &NaiveDate) -> (NaiveDate, NaiveDate) {
+    let start_of_month = NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap();
+    let next_month_year = if date.month() == 12 {
+        date.year() + 1
+    } else {
+        date.year()
+    };
+    let next_month = if date.month() == 12 {
+        1
+    } else {
+        date.month() + 1
+    };
+
+    let first_of_next_month = NaiveDate::from_ymd_opt(next_month_year, next_month, 1).unwrap();
+    let end_of_month = first_of_next_month - Duration::days(1);
+
+    (start_of_month, end_of_month)
+}
+
+fn get_year_bounds(date: &NaiveDate) -> (NaiveDate, NaiveDate) {
+    let year = date.year();
+
+    // Years always start on Jan 1st and end on Dec 31st
+    let start_of_year = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+    let end_of_year = NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
+
+    (start_of_year, end_of_year)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn bounds_of(input: &str, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+        input.parse::<TimeRange>().unwrap().bounds(&today)
+    }
+
+    #[test]
+    fn test_named_ranges() {
+        let today = day(2025, 3, 12);
+
+        assert_eq!(bounds_of("today", today), (today, today));
+        assert_eq!(
+            bounds_of("yesterday", today),
+            (day(2025, 3, 11), day(2025, 3, 11))
+        );
+        assert_eq!(
+            bounds_of("this-week", today),
+            (day(2025, 3, 10), day(2025, 3, 16))
+        );
+        assert_eq!(
+            bounds_of("last-week", today),
+            (day(2025, 3, 3), day(2025, 3, 9))
+        );
+        assert_eq!(
+            bounds_of("This-Month", today),
+            (day(2025, 3, 1), day(2025, 3, 31))
+        );
+        assert_eq!(
+            bounds_of("this-year", today),
+            (day(2025, 1, 1), day(2025, 12, 31))
+        );
+    }
+
+    #[test]
+    fn test_last_month_wraps_around_the_year() {
+        assert_eq!(
+            bounds_of("last-month", day(2025, 1, 20)),
+            (day(2024, 12, 1), day(2024, 12, 31))
+        );
+        assert_eq!(
+            bounds_of("last-month", day(2024, 3, 31)),
+            (day(2024, 2, 1), day(2024, 2, 29))
+        );
+    }
+
+    #[test]
+    fn test_last_year() {
+        assert_eq!(
+            bounds_of("last-year", day(2025, 6, 1)),
+            (day(2024, 1, 1), day(2024, 12, 31))
+        );
+    }
+
+    #[test]
+    fn test_last_n_days_includes_today() {
+        assert_eq!(
+            bounds_of("last-7-days", day(2025, 3, 12)),
+            (day(2025, 3, 6), day(2025, 3, 12))
+        );
+        assert_eq!(
+            bounds_of("last-1-days", day(2025, 3, 12)),
+            (day(2025, 3, 12), day(2025, 3, 12))
+        );
+        assert!("last-0-days".parse::<TimeRange>().is_err());
+        assert!("last-36525-days".parse::<TimeRange>().is_ok());
+        assert!("last-36526-days".parse::<TimeRange>().is_err());
+        assert!("last-4000000000-days".parse::<TimeRange>().is_err());
+        assert!("last-99999999999-days".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn test_quarters() {
+        let today = day(2026, 1, 1);
+        assert_eq!(
+            bounds_of("2025-Q1", today),
+            (day(2025, 1, 1), day(2025, 3, 31))
+        );
+        assert_eq!(
+            bounds_of("2025-q4", today),
+            (day(2025, 10, 1), day(2025, 12, 31))
+        );
+        assert!("2025-Q5".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn test_iso_weeks() {
+        let today = day(2026, 1, 1);
+        assert_eq!(
+            bounds_of("2025-W11", today),
+            (day(2025, 3, 10), day(2025, 3, 16))
+        );
+        // ISO week 1 of 2025 starts in 2024
+        assert_eq!(
+            bounds_of("2025-W01", today),
+            (day(2024, 12, 30), day(2025, 1, 5))
+        );
+        assert!("2025-W53".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn test_months() {
+        let today = day(2026, 1, 1);
+        assert_eq!(
+            bounds_of("2024-02", today),
+            (day(2024, 2, 1), day(2024, 2, 29))
+        );
+        assert!("2025-13".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn test_unknown_range() {
+        assert!("next-week".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn test_group_by_week_cuts_off_at_range_boundaries() {
+        let periods = GroupBy::Week.periods(&day(2025, 3, 12), &day(2025, 3, 25));
+
+        assert_eq!(
+            periods,
+            vec![
+                (day(2025, 3, 12), day(2025, 3, 16)),
+                (day(2025, 3, 17), day(2025, 3, 23)),
+                (day(2025, 3, 24), day(2025, 3, 25)),
+            ]
+        );
+        assert_eq!(GroupBy::Week.label(&periods[0].0), "2025-W11");
+    }
+
+    #[test]
+    fn test_group_by_month_and_day() {
+        let months = GroupBy::Month.periods(&day(2024, 12, 15), &day(2025, 2, 10));
+        assert_eq!(
+            months,
+            vec![
+                (day(2024, 12, 15), day(2024, 12, 31)),
+                (day(2025, 1, 1), day(2025, 1, 31)),
+                (day(2025, 2, 1), day(2025, 2, 10)),
+            ]
+        );
+        assert_eq!(GroupBy::Month.label(&months[1].0), "2025-01");
+
+        let days = GroupBy::Day.periods(&day(2025, 3, 1), &day(2025, 3, 3));
+        assert_eq!(days.len(), 3);
+        assert_eq!(GroupBy::Day.label(&days[2].0), "2025-03-03");
+    }
+}
@@ -1,71 +1,479 @@
+use clap::ValueEnum;
+use serde::Deserialize;
 use std::collections::HashMap;
 
-use crate::{block::Block, day_plan};
+use crate::{
+    block::Block,
+    day_plan,
+    tag::{Tag, TagFilter, TagPattern, filter_tag},
+};
 
 pub struct TagTime {
     pub tag: String,
-    pub minutes: u16,
+    pub minutes: u32,
     pub sub_tags: Vec<TagTime>,
 }
 
-pub fn minutes_to_hours_minutes(total_duration_today: u16) -> (u16, u16) {
+pub fn minutes_to_hours_minutes(total_duration_today: u32) -> (u32, u32) {
     let hours = total_duration_today / 60;
     let minutes = total_duration_today % 60;
     (hours, minutes)
 }
 
-pub fn time_per_tag(all_blocks: &Vec<&Block>, level: usize) -> Vec<TagTime> {
-    let mut groups: HashMap<&str, Vec<&Block>> = HashMap::new();
-    for b in all_blocks {
-        for tag in &b.tags {
-            match tag.tagls.get(level) {
-                Some(tagl) => {
-                    groups.entry(&tagl).or_insert(Vec::new()).push(&b);
-                }
-                _ => {}
+/// Formats a duration in minutes as `HH:MM`. Hours are not capped, so
+/// long periods show e.g. `1250:30`. With `hours_per_day`, full days of
+/// that length are split off, e.g. `3d 04:30` for 8 hour work days.
+pub fn format_duration(total_minutes: u32, hours_per_day: Option<u32>) -> String {
+    match hours_per_day {
+        Some(hours_per_day) if hours_per_day > 0 && total_minutes >= hours_per_day * 60 => {
+            let days = total_minutes / (hours_per_day * 60);
+            let (hours, minutes) = minutes_to_hours_minutes(total_minutes % (hours_per_day * 60));
+            format!("{}d {:02}:{:02}", days, hours, minutes)
+        }
+        _ => {
+            let (hours, minutes) = minutes_to_hours_minutes(total_minutes);
+            format!("{:02}:{:02}", hours, minutes)
+        }
+    }
+}
+
+/// Decides which blocks are left out of reports, e.g. breaks. A block is
+/// excluded when one of its tags matches an `exclude` pattern, unless
+/// that tag also matches an `include` pattern. The summaries in this
+/// module count every block they are given, so excluded blocks need to
+/// be filtered out beforehand.
+#[derive(Debug, Default)]
+pub struct TagExclusions {
+    pub exclude: Vec<TagPattern>,
+    pub include: Vec<TagPattern>,
+}
+
+impl TagExclusions {
+    pub fn excludes(&self, block: &Block) -> bool {
+        block.tags.iter().any(|tag| {
+            self.exclude.iter().any(|p| p.matches(tag))
+                && !self.include.iter().any(|p| p.matches(tag))
+        })
+    }
+}
+
+/// How the time of a block with several tags is attributed to them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Allocation {
+    /// Count the full time for each tag, so tags may add up to more than the total
+    #[default]
+    Full,
+    /// Split the time evenly among the tags
+    Split,
+    /// Count the time only for the first tag
+    First,
+    /// Split by weights like `@tag:60%`, unweighted tags share the rest evenly
+    Weighted,
+}
+
+impl Allocation {
+    /// Returns the minutes of `block` attributed to each of its tags, in
+    /// the order of `block.tags`. Except for `Full`, they add up to the
+    /// duration of the block.
+    pub fn minutes_per_tag(&self, block: &Block) -> Vec<u32> {
+        let tag_count = block.tags.len();
+        match self {
+            Allocation::Full => vec![block.duration; tag_count],
+            Allocation::Split => apportion(block.duration, &vec![1; tag_count]),
+            Allocation::First => (0..tag_count)
+                .map(|i| if i == 0 { block.duration } else { 0 })
+                .collect(),
+            Allocation::Weighted => {
+                let weighted: u64 = block
+                    .tags
+                    .iter()
+                    .filter_map(|t| t.weight)
+                    .map(u64::from)
+                    .sum();
+                let unweighted = block.tags.iter().filter(|t| t.weight.is_none()).count() as u64;
+                let weights: Vec<u64> = if unweighted == 0 || weighted >= 100 {
+                    // Nothing left to share, scale the weights to the whole block
+                    block
+                        .tags
+                        .iter()
+                        .map(|t| t.weight.unwrap_or(0) as u64)
+                        .collect()
+                } else {
+                    block
+                        .tags
+                        .iter()
+                        .map(|t| match t.weight {
+                            Some(w) => w as u64 * unweighted,
+                            None => 100 - weighted,
+                        })
+                        .collect()
+                };
+                apportion(block.duration, &weights)
             }
         }
     }
 
-    let mut timings = Vec::new();
-    for (tagl, blocks) in groups {
-        // ignore @break/... tags
-        if level == 0 && tagl == "break" {
-            continue;
+    /// Returns the tags of `block` that match `filters`, re-rooted like
+    /// `filter_tag`, with the minutes attributed to each. The minutes are
+    /// shared among all tags of the block, so a tag gets the same minutes
+    /// whether or not the other tags are filtered out.
+    pub fn minutes_per_matching_tag(
+        &self,
+        block: &Block,
+        filters: &[TagFilter],
+    ) -> Vec<(Tag, u32)> {
+        block
+            .tags
+            .iter()
+            .zip(self.minutes_per_tag(block))
+            .filter_map(|(tag, minutes)| Some((filter_tag(tag, filters)?, minutes)))
+            .collect()
+    }
+
+    /// Returns the minutes of `block` that count for the tags matching
+    /// `filters`, or its whole duration without any filters.
+    pub fn matching_minutes(&self, block: &Block, filters: &[TagFilter]) -> u32 {
+        if filters.is_empty() {
+            return block.duration;
         }
+        let minutes: u32 = self
+            .minutes_per_matching_tag(block, filters)
+            .iter()
+            .map(|(_, m)| m)
+            .sum();
+        // With `Full`, several matching tags share the same minutes
+        minutes.min(block.duration)
+    }
+}
+
+/// Distributes `total` proportionally to `weights`, such that the parts
+/// add up to `total` exactly (largest remainder method).
+fn apportion(total: u32, weights: &[u64]) -> Vec<u32> {
+    let weight_sum: u64 = weights.iter().sum();
+    if weight_sum == 0 {
+        return vec![0; weights.len()];
+    }
 
-        let total_accumulated = total_minutes(&blocks);
+    let mut parts: Vec<u32> = weights
+        .iter()
+        .map(|w| (total as u64 * w / weight_sum) as u32)
+        .collect();
+    let mut by_remainder: Vec<usize> = (0..weights.len()).collect();
+    by_remainder.sort_by_key(|&i| std::cmp::Reverse(total as u64 * weights[i] % weight_sum));
+
+    let missing = total - parts.iter().sum::<u32>();
+    for &i in by_remainder.iter().take(missing as usize) {
+        parts[i] += 1;
+    }
+    parts
+}
+
+/// Label of the entries that sum up the time of blocks without a tag, or
+/// without a deeper tag level, respectively.
+pub const UNTAGGED: &str = "(untagged)";
+
+/// The part of a block's time that falls under the tag currently being
+/// summarized.
+struct TaggedTime<'a> {
+    minutes: u32,
+    /// The block's tags below that tag, with the minutes attributed to each
+    tags: Vec<(&'a [String], u32)>,
+}
+
+/// Sums up the time of all blocks per tag, recursively for each level
+/// of the tag hierarchy. Time that isn't covered by any (deeper) tag is
+/// reported in an `UNTAGGED` entry, so that the entries of each level
+/// add up to the time of their parent (unless blocks are counted fully
+/// for several tags with `Allocation::Full`). With `filters`, only the
+/// time of the matching tags is counted and they become the top level.
+pub fn time_per_tag(
+    all_blocks: &[&Block],
+    allocation: Allocation,
+    filters: &[TagFilter],
+) -> Vec<TagTime> {
+    let matching_tags: Vec<(u32, Vec<(Tag, u32)>)> = all_blocks
+        .iter()
+        .map(|b| {
+            (
+                allocation.matching_minutes(b, filters),
+                allocation.minutes_per_matching_tag(b, filters),
+            )
+        })
+        .collect();
+    let tagged_times = matching_tags
+        .iter()
+        .map(|(minutes, tags)| TaggedTime {
+            minutes: *minutes,
+            tags: tags
+                .iter()
+                .filter(|(_, minutes)| *minutes > 0)
+                .map(|(tag, minutes)| (tag.tagls.as_slice(), *minutes))
+                .collect(),
+        })
+        .collect();
+    time_per_tagl(tagged_times, 0)
+}
+
+fn time_per_tagl(tagged_times: Vec<TaggedTime>, level: usize) -> Vec<TagTime> {
+    let mut groups: HashMap<&str, Vec<TaggedTime>> = HashMap::new();
+    let mut untagged_minutes = 0;
+    for tt in tagged_times {
+        let mut tags_per_tagl: HashMap<&str, Vec<(&[String], u32)>> = HashMap::new();
+        for (tagls, minutes) in &tt.tags {
+            if let Some(tagl) = tagls.get(level) {
+                tags_per_tagl
+                    .entry(tagl)
+                    .or_default()
+                    .push((tagls, *minutes));
+            }
+        }
+
+        let tagged_minutes: u32 = tags_per_tagl.values().flatten().map(|(_, m)| m).sum();
+        untagged_minutes += tt.minutes - tagged_minutes.min(tt.minutes);
+
+        for (tagl, tags) in tags_per_tagl {
+            // A block with several tags on the same level, e.g. `@p/a @p/b`,
+            // counts only once for their common parent `p`
+            let minutes = tags.iter().map(|(_, m)| m).sum::<u32>().min(tt.minutes);
+            groups
+                .entry(tagl)
+                .or_default()
+                .push(TaggedTime { minutes, tags });
+        }
+    }
+
+    let mut timings = Vec::new();
+    for (tagl, tagged_times) in groups {
         timings.push(TagTime {
             tag: tagl.to_string(),
-            minutes: total_accumulated,
-            sub_tags: time_per_tag(&blocks.into(), level + 1),
+            minutes: tagged_times.iter().map(|tt| tt.minutes).sum(),
+            sub_tags: time_per_tagl(tagged_times, level + 1),
         });
     }
     // Sort in DESC order
     timings.sort_by(|l, r| r.minutes.cmp(&l.minutes));
+
+    // Below the top level, only show untagged time next to tagged time
+    if untagged_minutes > 0 && (level == 0 || !timings.is_empty()) {
+        timings.push(TagTime {
+            tag: UNTAGGED.to_string(),
+            minutes: untagged_minutes,
+            sub_tags: Vec::new(),
+        });
+    }
     timings
 }
 
-pub fn total_minutes(blocks: &Vec<&Block>) -> u16 {
-    blocks.iter().fold(0, |total, b| total + b.duration)
+/// Finds the timing of the tag with the given levels in a tree as
+/// returned by `time_per_tag`.
+pub fn find_tag_time<'a>(tag_timings: &'a [TagTime], tagls: &[String]) -> Option<&'a TagTime> {
+    let (first, rest) = tagls.split_first()?;
+    let tt = tag_timings.iter().find(|tt| &tt.tag == first)?;
+    if rest.is_empty() {
+        Some(tt)
+    } else {
+        find_tag_time(&tt.sub_tags, rest)
+    }
 }
 
-pub fn total_time_spent(all_of_day: &Vec<day_plan::DayPlan>) -> u16 {
-    let total_duration_today = all_of_day.iter().fold(0, |total_duration, dp| {
-        total_duration
-            + dp.only_original_blocks().iter().fold(0, |acc, b| {
-                if b.tags
-                    .iter()
-                    .any(|tag| match tag.tagls.get(0).map(|s| s.as_ref()) {
-                        Some("break") => true,
-                        _ => false,
-                    })
-                {
-                    acc
-                } else {
-                    acc + b.duration
-                }
+/// Sums up the time of the original blocks of the day plans. With
+/// `filters`, only the time of the matching tags is counted.
+pub fn total_time_spent(
+    all_of_day: &[day_plan::DayPlan],
+    allocation: Allocation,
+    filters: &[TagFilter],
+) -> u32 {
+    all_of_day
+        .iter()
+        .flat_map(|dp| dp.only_original_blocks_slice())
+        .map(|b| allocation.matching_minutes(b, filters))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day_plan::{DayPlan, Source};
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0, None), "00:00");
+        assert_eq!(format_duration(125, None), "02:05");
+        assert_eq!(format_duration(100 * 60 + 1, None), "100:01");
+        assert_eq!(format_duration(125, Some(8)), "02:05");
+        assert_eq!(format_duration(8 * 60, Some(8)), "1d 00:00");
+        assert_eq!(format_duration(3 * 24 * 60 + 270, Some(24)), "3d 04:30");
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let exclusions = TagExclusions {
+            exclude: vec!["break".parse().unwrap(), "private/*".parse().unwrap()],
+            include: vec!["private/health".parse().unwrap()],
+        };
+        let excludes = |desc: &str| exclusions.excludes(&Block::new("", "Work", desc));
+
+        assert!(excludes("Coffee @break"));
+        assert!(excludes("Coffee @break/long @proj/alpha"));
+        assert!(excludes("Shopping @private/errands"));
+        assert!(!excludes("Doctor @private/health"));
+        assert!(!excludes("Private time @private"));
+        assert!(!excludes("Work @proj/alpha"));
+        assert!(!excludes("No tags at all"));
+    }
+
+    fn summary_of(blocks: &[Block]) -> Vec<TagTime> {
+        time_per_tag(
+            &blocks.iter().collect::<Vec<&Block>>(),
+            Allocation::Full,
+            &[],
+        )
+    }
+
+    fn minutes_of(timings: &[TagTime], path: &[&str]) -> Option<u32> {
+        let path: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+        find_tag_time(timings, &path).map(|tt| tt.minutes)
+    }
+
+    #[test]
+    fn test_time_per_tag_hierarchy() {
+        let timings = summary_of(&[
+            Block::new("09:00 - 10:00", "Work", "Alpha @proj/alpha"),
+            Block::new("10:00 - 10:30", "Work", "Beta @proj/beta"),
+            Block::new("11:00 - 12:00", "Work", "Alpha again @proj/alpha/review"),
+        ]);
+
+        assert_eq!(timings.len(), 1);
+        assert_eq!(minutes_of(&timings, &["proj"]), Some(150));
+        assert_eq!(minutes_of(&timings, &["proj", "alpha"]), Some(120));
+        assert_eq!(minutes_of(&timings, &["proj", "beta"]), Some(30));
+        assert_eq!(minutes_of(&timings, &["proj", "alpha", "review"]), Some(60));
+        assert_eq!(minutes_of(&timings, &["proj", "alpha", UNTAGGED]), Some(60));
+        // Leaves have no untagged entry of their own
+        assert!(
+            find_tag_time(&timings, &["proj".into(), "beta".into()])
+                .unwrap()
+                .sub_tags
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_time_per_tag_only_follows_tags_below_parent() {
+        let timings = summary_of(&[Block::new(
+            "09:00 - 10:00",
+            "Work",
+            "Review @proj/beta @meetings",
+        )]);
+
+        assert_eq!(minutes_of(&timings, &["meetings"]), Some(60));
+        assert_eq!(minutes_of(&timings, &["meetings", "beta"]), None);
+        assert_eq!(minutes_of(&timings, &["proj", "beta"]), Some(60));
+    }
+
+    #[test]
+    fn test_time_per_tag_counts_block_once_per_parent() {
+        let timings = summary_of(&[Block::new(
+            "09:00 - 10:00",
+            "Work",
+            "Both @proj/alpha @proj/beta",
+        )]);
+
+        assert_eq!(minutes_of(&timings, &["proj"]), Some(60));
+        assert_eq!(minutes_of(&timings, &["proj", "alpha"]), Some(60));
+        assert_eq!(minutes_of(&timings, &["proj", "beta"]), Some(60));
+    }
+
+    #[test]
+    fn test_time_per_tag_reports_untagged_time() {
+        let timings = summary_of(&[
+            Block::new("09:00 - 10:00", "Work", "Alpha @proj/alpha"),
+            Block::new("10:00 - 10:30", "Work", "Some project work @proj"),
+            Block::new("11:00 - 11:45", "Work", "Forgot to tag"),
+        ]);
+
+        assert_eq!(timings.last().unwrap().tag, UNTAGGED);
+        assert_eq!(minutes_of(&timings, &[UNTAGGED]), Some(45));
+        assert_eq!(minutes_of(&timings, &["proj"]), Some(90));
+        assert_eq!(minutes_of(&timings, &["proj", UNTAGGED]), Some(30));
+        assert_eq!(timings.iter().map(|tt| tt.minutes).sum::<u32>(), 135);
+    }
+
+    #[test]
+    fn test_allocation_modes() {
+        let block = Block::new("09:00 - 10:40", "Work", "@a @b @c");
+
+        assert_eq!(
+            Allocation::Full.minutes_per_tag(&block),
+            vec![100, 100, 100]
+        );
+        assert_eq!(Allocation::Split.minutes_per_tag(&block), vec![34, 33, 33]);
+        assert_eq!(Allocation::First.minutes_per_tag(&block), vec![100, 0, 0]);
+        assert_eq!(
+            Allocation::Weighted.minutes_per_tag(&block),
+            vec![34, 33, 33]
+        );
+    }
+
+    #[test]
+    fn test_weighted_allocation() {
+        let weighted = |desc: &str| {
+            Allocation::Weighted.minutes_per_tag(&Block::new("09:00 - 11:00", "Work", desc))
+        };
+
+        assert_eq!(weighted("@proj/a:60% @meetings"), vec![72, 48]);
+        assert_eq!(weighted("@proj/a:50% @meetings @mail"), vec![60, 30, 30]);
+        assert_eq!(weighted("@proj/a:30% @meetings:10%"), vec![90, 30]);
+        assert_eq!(weighted("@a:80% @b:40% @c"), vec![80, 40, 0]);
+    }
+
+    #[test]
+    fn test_time_per_tag_reconciles_with_split_allocation() {
+        let blocks = [
+            Block::new("09:00 - 10:00", "Work", "Review @proj/a @meetings"),
+            Block::new("10:00 - 11:00", "Work", "Planning @proj/a:75% @proj/b"),
+        ];
+        let timings = time_per_tag(
+            &blocks.iter().collect::<Vec<&Block>>(),
+            Allocation::Weighted,
+            &[],
+        );
+
+        assert_eq!(minutes_of(&timings, &["proj"]), Some(90));
+        assert_eq!(minutes_of(&timings, &["proj", "a"]), Some(75));
+        assert_eq!(minutes_of(&timings, &["proj", "b"]), Some(15));
+        assert_eq!(minutes_of(&timings, &["meetings"]), Some(30));
+        assert_eq!(timings.iter().map(|tt| tt.minutes).sum::<u32>(), 120);
+
+        let first = time_per_tag(
+            &blocks.iter().collect::<Vec<&Block>>(),
+            Allocation::First,
+            &[],
+        );
+        assert_eq!(minutes_of(&first, &["proj", "a"]), Some(120));
+        assert_eq!(minutes_of(&first, &["meetings"]), None);
+    }
+
+    #[test]
+    fn test_total_time_spent_beyond_u16_range() {
+        // A full working year is well beyond the ~1092 hours a u16 can hold
+        let day_plans: Vec<DayPlan> = (0..250)
+            .map(|_| DayPlan {
+                origin: "Work".to_string(),
+                blocks: vec![Block::new("09:00 - 17:00", "Work", "Work @job")],
+                day: None,
+                source: Source::ICalendar,
             })
-    });
-    total_duration_today
+            .collect();
+
+        assert_eq!(
+            total_time_spent(&day_plans, Allocation::Full, &[]),
+            250 * 8 * 60
+        );
+        assert_eq!(
+            format_duration(total_time_spent(&day_plans, Allocation::Full, &[]), None),
+            "2000:00"
+        );
+    }
 }